@@ -0,0 +1,396 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::corporate_actions::CorporateActions;
+use crate::download_data::YQuote;
+use crate::process_data::Data;
+
+/// Which price series to run the analytics on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Adjustment {
+    /// whatever the vendor put in adjclose
+    #[default]
+    Vendor,
+    /// close as traded, nothing applied
+    Raw,
+    /// back-adjusted for splits only
+    Split,
+    /// back-adjusted for splits and dividends
+    TotalReturn(DividendMethod),
+}
+
+/// How a dividend is taken out of the prices before its ex-date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DividendMethod {
+    /// scale by (1 - dividend / previous close) - what yahoo and CRSP do, keeps returns intact
+    Multiplicative,
+    /// subtract the dividend - keeps price differences intact, can go negative far back
+    Additive,
+}
+
+impl fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Adjustment::Vendor => "vendor",
+            Adjustment::Raw => "raw",
+            Adjustment::Split => "split",
+            Adjustment::TotalReturn(DividendMethod::Multiplicative) => "total",
+            Adjustment::TotalReturn(DividendMethod::Additive) => "total-additive",
+        })
+    }
+}
+
+impl FromStr for Adjustment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vendor" => Ok(Adjustment::Vendor),
+            "raw" => Ok(Adjustment::Raw),
+            "split" => Ok(Adjustment::Split),
+            "total" => Ok(Adjustment::TotalReturn(DividendMethod::Multiplicative)),
+            "total-additive" => Ok(Adjustment::TotalReturn(DividendMethod::Additive)),
+            _ => Err(format!(
+                "unknown adjustment '{}' - use vendor, raw, split, total or total-additive",
+                s
+            )),
+        }
+    }
+}
+
+/// Puts split-adjusted bars (like yahoo's) back to as traded: prices scaled up and volume down by
+/// every split dated after the bar. Yahoo's dividends are restated the same way, so they go back to
+/// the cash actually paid too. adjclose is left alone.
+pub fn unadjust_splits(quotes: &[YQuote], actions: &CorporateActions) -> (Data, CorporateActions) {
+    // product of the ratios of every split after `at`
+    let later = |at| {
+        actions
+            .splits
+            .iter()
+            .filter(|s| s.date > at)
+            .fold(Decimal::one(), |ratio, s| ratio * s.ratio())
+    };
+    let raw = quotes
+        .iter()
+        .map(|q| {
+            let ratio = later(q.timestamp);
+            let volume = if ratio.is_zero() {
+                q.volume
+            } else {
+                (Decimal::from(q.volume) / ratio)
+                    .round()
+                    .to_u64()
+                    .unwrap_or(q.volume)
+            };
+            YQuote {
+                open: q.open * ratio,
+                high: q.high * ratio,
+                low: q.low * ratio,
+                close: q.close * ratio,
+                volume,
+                ..q.clone()
+            }
+        })
+        .collect();
+    let mut actions = actions.clone();
+    for d in &mut actions.dividends {
+        d.amount *= later(d.ex_date);
+    }
+    (raw, actions)
+}
+
+/// Back-adjusts OHLC (and volume, for splits) so the latest bar is left as is and everything before
+/// each event is restated in today's terms. `split_adjusted` says the bars already have the splits in
+/// them (see `QuoteProvider::split_adjusted`) - they're taken back to as traded first, so no split gets
+/// applied twice. Raw hands back the bars as traded.
+/// The adjusted close also goes into `adjclose` (bar Raw, which keeps the vendor's).
+pub fn adjust(
+    quotes: &[YQuote],
+    actions: &CorporateActions,
+    adjustment: Adjustment,
+    split_adjusted: bool,
+) -> Data {
+    if adjustment == Adjustment::Vendor {
+        return quotes.to_vec();
+    }
+    let (mut adjusted, actions) = if split_adjusted {
+        unadjust_splits(quotes, actions)
+    } else {
+        (quotes.to_vec(), actions.clone())
+    };
+    let dividend_method = match adjustment {
+        Adjustment::Vendor | Adjustment::Raw => return adjusted,
+        Adjustment::Split => None,
+        Adjustment::TotalReturn(method) => Some(method),
+    };
+
+    // adjusted price = raw * factor + offset, built up walking backwards through the events
+    let mut factor = Decimal::one();
+    let mut offset = Decimal::zero();
+    let mut volume_factor = Decimal::one();
+    let mut splits = actions.splits.iter().rev().peekable();
+    let mut dividends = actions.dividends.iter().rev().peekable();
+
+    for q in adjusted.iter_mut().rev() {
+        // every event dated after this bar applies to it
+        while let Some(s) = splits.next_if(|s| s.date > q.timestamp) {
+            factor /= s.ratio();
+            volume_factor *= s.ratio();
+        }
+        while let Some(d) = dividends.next_if(|d| d.ex_date > q.timestamp) {
+            match dividend_method {
+                // q is the last bar before the ex-date, so its close is the "previous close"
+                Some(DividendMethod::Multiplicative) if !q.close.is_zero() => {
+                    factor *= Decimal::one() - d.amount / q.close
+                }
+                Some(DividendMethod::Additive) => offset -= d.amount * factor,
+                _ => {}
+            }
+        }
+        let apply = |p: Decimal| p * factor + offset;
+        q.open = apply(q.open);
+        q.high = apply(q.high);
+        q.low = apply(q.low);
+        q.close = apply(q.close);
+        q.adjclose = q.close;
+        q.volume = (Decimal::from(q.volume) * volume_factor)
+            .round()
+            .to_u64()
+            .unwrap_or(q.volume);
+    }
+    adjusted
+}
+
+/// How far our adjusted closes are from the vendor's adjclose.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub bars: usize,
+    pub max_abs_diff: Decimal,
+    /// as a fraction, eg 0.01 = 1%
+    pub max_pct_diff: Decimal,
+    pub mean_pct_diff: Decimal,
+    /// timestamp of the bar with the biggest % difference
+    pub worst_timestamp: Option<DateTime<Utc>>,
+    /// (timestamp, ours, vendor's) for every bar further apart than the tolerance
+    pub mismatches: Vec<(DateTime<Utc>, Decimal, Decimal)>,
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bars, {} over tolerance, max diff {} ({}%), mean diff {}%",
+            self.bars,
+            self.mismatches.len(),
+            self.max_abs_diff.round_dp(4),
+            (self.max_pct_diff * Decimal::from(100)).round_dp(4),
+            (self.mean_pct_diff * Decimal::from(100)).round_dp(4),
+        )
+    }
+}
+
+/// Compares `adjusted` closes (from `adjust`) bar by bar against the vendor's adjclose in `vendor`.
+pub fn validate_against_vendor(
+    vendor: &[YQuote],
+    adjusted: &[YQuote],
+    tolerance_pct: Decimal,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let mut pct_sum = Decimal::zero();
+    for (v, ours) in vendor.iter().zip(adjusted) {
+        let abs_diff = (ours.adjclose - v.adjclose).abs();
+        let pct_diff = if v.adjclose.is_zero() {
+            Decimal::zero()
+        } else {
+            abs_diff / v.adjclose.abs()
+        };
+        report.bars += 1;
+        pct_sum += pct_diff;
+        report.max_abs_diff = report.max_abs_diff.max(abs_diff);
+        if pct_diff > report.max_pct_diff || report.worst_timestamp.is_none() {
+            report.max_pct_diff = pct_diff;
+            report.worst_timestamp = Some(v.timestamp);
+        }
+        if pct_diff > tolerance_pct {
+            report
+                .mismatches
+                .push((v.timestamp, ours.adjclose, v.adjclose));
+        }
+    }
+    if report.bars > 0 {
+        report.mean_pct_diff = pct_sum / Decimal::from(report.bars);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::corporate_actions::{Dividend, Split};
+
+    fn day(d: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 1, d).and_hms(21, 0, 0)
+    }
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    /// (day, close, volume) - open, high and low are the close
+    fn bars(rows: &[(u32, &str, u64)]) -> Data {
+        rows.iter()
+            .map(|&(d, close, volume)| {
+                let close = dec(close);
+                YQuote {
+                    timestamp: day(d),
+                    open: close,
+                    high: close,
+                    low: close,
+                    volume,
+                    close,
+                    adjclose: close,
+                }
+            })
+            .collect()
+    }
+
+    fn closes(quotes: &[YQuote]) -> Vec<Decimal> {
+        quotes.iter().map(|q| q.close).collect()
+    }
+
+    fn decimals(values: &[&str]) -> Vec<Decimal> {
+        values.iter().map(|v| dec(v)).collect()
+    }
+
+    /// a 2:1 split on the 6th, plus the given dividends
+    fn actions(dividends: &[(u32, &str)]) -> CorporateActions {
+        CorporateActions {
+            dividends: dividends
+                .iter()
+                .map(|&(d, amount)| Dividend {
+                    ex_date: day(d),
+                    amount: dec(amount),
+                })
+                .collect(),
+            splits: vec![Split {
+                date: day(6),
+                numerator: 2,
+                denominator: 1,
+            }],
+        }
+    }
+
+    /// as traded: 100, 110, then 50 after the split and 54 after the dividend
+    fn traded() -> Data {
+        bars(&[
+            (4, "100", 1000),
+            (5, "110", 1000),
+            (6, "50", 2000),
+            (7, "54", 2000),
+        ])
+    }
+
+    #[test]
+    fn unadjust_splits_goes_back_to_as_traded() {
+        // what yahoo sends: prices halved and volume doubled before the split, and the
+        // pre-split dividend halved too
+        let yahoo = bars(&[
+            (4, "50", 2000),
+            (5, "55", 2000),
+            (6, "50", 2000),
+            (7, "54", 2000),
+        ]);
+        let (raw, raw_actions) = unadjust_splits(&yahoo, &actions(&[(5, "0.25"), (7, "1")]));
+        assert_eq!(closes(&raw), closes(&traded()));
+        let volumes: Vec<u64> = raw.iter().map(|q| q.volume).collect();
+        assert_eq!(volumes, vec![1000, 1000, 2000, 2000]);
+        let paid: Vec<Decimal> = raw_actions.dividends.iter().map(|d| d.amount).collect();
+        assert_eq!(paid, decimals(&["0.5", "1"]));
+        // adjclose isn't touched
+        assert_eq!(raw[0].adjclose, dec("50"));
+    }
+
+    #[test]
+    fn split_adjustment() {
+        let adjusted = adjust(&traded(), &actions(&[(7, "1")]), Adjustment::Split, false);
+        assert_eq!(closes(&adjusted), decimals(&["50", "55", "50", "54"]));
+        let volumes: Vec<u64> = adjusted.iter().map(|q| q.volume).collect();
+        assert_eq!(volumes, vec![2000, 2000, 2000, 2000]);
+        assert!(adjusted.iter().all(|q| q.adjclose == q.close));
+
+        // bars that already have the split in them don't get it twice
+        let yahoo = adjust(&adjusted, &actions(&[]), Adjustment::Split, true);
+        assert_eq!(closes(&yahoo), closes(&adjusted));
+    }
+
+    #[test]
+    fn total_return_multiplicative() {
+        // factor before the ex-date: 1 - 1/50 = 0.98, then halved again before the split
+        let adjusted = adjust(
+            &traded(),
+            &actions(&[(7, "1")]),
+            Adjustment::TotalReturn(DividendMethod::Multiplicative),
+            false,
+        );
+        assert_eq!(closes(&adjusted), decimals(&["49", "53.9", "49", "54"]));
+    }
+
+    #[test]
+    fn total_return_additive() {
+        // $1 off everything before the ex-date, in post-split terms
+        let adjusted = adjust(
+            &traded(),
+            &actions(&[(7, "1")]),
+            Adjustment::TotalReturn(DividendMethod::Additive),
+            false,
+        );
+        assert_eq!(closes(&adjusted), decimals(&["49", "54", "49", "54"]));
+    }
+
+    #[test]
+    fn vendor_and_raw() {
+        let mut vendor = traded();
+        vendor[0].adjclose = dec("49");
+        let actions = actions(&[(7, "1")]);
+        assert_eq!(adjust(&vendor, &actions, Adjustment::Vendor, true), vendor);
+        // back to as traded, the vendor's adjclose stays
+        let yahoo = adjust(&vendor, &actions, Adjustment::Split, false);
+        let raw = adjust(&yahoo, &actions, Adjustment::Raw, true);
+        assert_eq!(closes(&raw), closes(&vendor));
+        assert_eq!(raw[0].adjclose, dec("50"));
+    }
+
+    #[test]
+    fn validation_flags_bars_over_the_tolerance() {
+        let ours = adjust(
+            &traded(),
+            &actions(&[(7, "1")]),
+            Adjustment::TotalReturn(DividendMethod::Multiplicative),
+            false,
+        );
+        let mut vendor = traded();
+        // 0.2% and 2% off ours, the rest spot on
+        for (q, adjclose) in vendor.iter_mut().zip(&["49.098", "54.978", "49", "54"]) {
+            q.adjclose = dec(adjclose);
+        }
+        let report = validate_against_vendor(&vendor, &ours, dec("0.005"));
+        assert_eq!(report.bars, 4);
+        assert_eq!(
+            report.mismatches,
+            vec![(day(5), dec("53.9"), dec("54.978"))]
+        );
+        assert_eq!(report.max_abs_diff, dec("1.078"));
+        assert_eq!(report.max_pct_diff.round_dp(6), dec("0.019608"));
+        assert_eq!(report.worst_timestamp, Some(day(5)));
+
+        let exact = validate_against_vendor(&ours, &ours, dec("0.005"));
+        assert!(exact.mismatches.is_empty());
+        assert!(exact.max_pct_diff.is_zero());
+    }
+}
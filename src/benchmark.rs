@@ -0,0 +1,281 @@
+use chrono_tz::Tz;
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::download_data::{History, YQuote};
+use crate::interval::Interval;
+use crate::metadata::TickerInfo;
+use crate::price_frame::{Join, PriceFrame};
+use crate::risk::{
+    correlation, covariance, mean, per_bar, simple_returns, sqrt_bars_per_year, std_dev,
+};
+use crate::streaming::PriceField;
+
+/// The series every ticker gets compared against, fetched once per run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Benchmark {
+    pub info: TickerInfo,
+    pub history: History,
+    /// annual, as a fraction - for jensen's alpha
+    pub risk_free_rate: Decimal,
+}
+
+impl Benchmark {
+    /// zone the benchmark's bars are dated in
+    pub fn timezone(&self) -> Tz {
+        self.history
+            .timezone
+            .or(self.info.timezone)
+            .unwrap_or(Tz::UTC)
+    }
+}
+
+/// A ticker against the benchmark, over the bars both have. Annualised per the interval's bars per year.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BenchmarkStats {
+    pub benchmark: String,
+    /// bars both series have
+    pub bars: usize,
+    pub beta: Option<Decimal>,
+    /// jensen's alpha - return over what beta and the risk-free rate account for, per year
+    pub alpha: Option<Decimal>,
+    pub correlation: Option<Decimal>,
+    pub r_squared: Option<Decimal>,
+    /// standard deviation of the return difference, per year
+    pub tracking_error: Option<Decimal>,
+    /// active return per year over tracking error
+    pub information_ratio: Option<Decimal>,
+    /// mean return on the benchmark's up bars over the benchmark's mean on them
+    pub up_capture: Option<Decimal>,
+    /// same for the down bars
+    pub down_capture: Option<Decimal>,
+}
+
+/// mean of `r` over the bars where `select(m)`, against the mean of `m` over them
+fn capture(r: &[Decimal], m: &[Decimal], select: impl Fn(&Decimal) -> bool) -> Option<Decimal> {
+    let (r, m): (Vec<Decimal>, Vec<Decimal>) = r
+        .iter()
+        .zip(m)
+        .filter(|(_, m)| select(m))
+        .map(|(r, m)| (*r, *m))
+        .unzip();
+    let m = mean(&m)?;
+    if m.is_zero() {
+        return None;
+    }
+    Some(mean(&r)? / m)
+}
+
+impl BenchmarkStats {
+    /// names of the summary columns `row` fills in
+    pub fn columns() -> Vec<String> {
+        [
+            "benchmark",
+            "beta",
+            "alpha %",
+            "correlation",
+            "r2",
+            "tracking error %",
+            "info ratio",
+            "up capture",
+            "down capture",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+    }
+
+    /// Compares the adjcloses of `quotes` (dated in `timezone`) with the benchmark's, over the bars both
+    /// have - a missing bar on either side turns the next return into a two bar return on both.
+    pub fn compute(
+        quotes: &[YQuote],
+        timezone: Tz,
+        benchmark: &Benchmark,
+        interval: Interval,
+    ) -> Self {
+        // fixed column names - the ticker can be the benchmark itself
+        let frame = PriceFrame::join(
+            interval,
+            Join::Inner,
+            vec![
+                ("ticker".to_string(), quotes.to_vec(), timezone),
+                (
+                    "benchmark".to_string(),
+                    benchmark.history.quotes.clone(),
+                    benchmark.timezone(),
+                ),
+            ],
+        );
+        let joined = frame
+            .pair("ticker", "benchmark", PriceField::AdjClose)
+            .unwrap_or_default();
+        let r = simple_returns(&joined.iter().map(|(_, p, _)| *p).collect::<Vec<_>>());
+        let m = simple_returns(&joined.iter().map(|(_, _, b)| *b).collect::<Vec<_>>());
+
+        let beta = match (covariance(&r, &m), covariance(&m, &m)) {
+            (Some(cov), Some(var)) if !var.is_zero() => Some(cov / var),
+            _ => None,
+        };
+        let rf = per_bar(benchmark.risk_free_rate, interval);
+        let bars_per_year =
+            Decimal::from_f64(interval.bars_per_year()).unwrap_or_else(Decimal::one);
+        let alpha = match (beta, mean(&r), mean(&m)) {
+            (Some(beta), Some(r), Some(m)) => Some((r - rf - beta * (m - rf)) * bars_per_year),
+            _ => None,
+        };
+        let correlation = correlation(&r, &m);
+        let active: Vec<Decimal> = r.iter().zip(&m).map(|(r, m)| r - m).collect();
+        let tracking_error = std_dev(&active).map(|sd| sd * sqrt_bars_per_year(interval));
+        let information_ratio = match (mean(&active), tracking_error) {
+            (Some(active), Some(te)) if !te.is_zero() => Some(active * bars_per_year / te),
+            _ => None,
+        };
+        Self {
+            benchmark: benchmark.info.symbol.clone(),
+            bars: joined.len(),
+            beta,
+            alpha,
+            r_squared: correlation.map(|c| c * c),
+            correlation,
+            tracking_error,
+            information_ratio,
+            up_capture: capture(&r, &m, |m| m.is_sign_positive() && !m.is_zero()),
+            down_capture: capture(&r, &m, |m| m.is_sign_negative() && !m.is_zero()),
+        }
+    }
+
+    /// the summary columns, rounded - blank where there weren't enough common bars
+    pub fn row(&self) -> Vec<String> {
+        let pct = |v: Option<Decimal>| v.map(|v| v * Decimal::from(100));
+        let mut row = vec![self.benchmark.clone()];
+        row.extend(
+            [
+                self.beta,
+                pct(self.alpha),
+                self.correlation,
+                self.r_squared,
+                pct(self.tracking_error),
+                self.information_ratio,
+                self.up_capture,
+                self.down_capture,
+            ]
+            .iter()
+            .map(|v| v.map_or(String::new(), |v| v.round_dp(2).to_string())),
+        );
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::risk::annualized_volatility;
+
+    /// daily bars from the 4th of january, (day offset, adjclose)
+    fn bars(prices: &[(i64, &str)]) -> Vec<YQuote> {
+        prices
+            .iter()
+            .map(|&(d, price)| {
+                let price = Decimal::from_str(price).unwrap();
+                YQuote {
+                    timestamp: Utc.ymd(2021, 1, 4).and_hms(21, 0, 0) + Duration::days(d),
+                    open: price,
+                    high: price,
+                    low: price,
+                    volume: 100,
+                    close: price,
+                    adjclose: price,
+                }
+            })
+            .collect()
+    }
+
+    fn assert_close(got: Option<Decimal>, expected: Decimal) {
+        let got = got.unwrap();
+        assert!(
+            (got - expected).abs() < Decimal::new(1, 12),
+            "{} vs {}",
+            got,
+            expected
+        );
+    }
+
+    // benchmark returns of 1%, -1%, 2% (over the gap on day 3) and -2%; the ticker's are twice that on
+    // the same days. Day 3 is only in the ticker and day 8 only in the benchmark, both with prices that
+    // would wreck the numbers if they got in.
+    #[test]
+    fn twice_the_benchmark() {
+        let benchmark = Benchmark {
+            info: TickerInfo::unknown("SPY"),
+            history: History {
+                quotes: bars(&[
+                    (0, "100"),
+                    (1, "101"),
+                    (2, "99.99"),
+                    (4, "101.9898"),
+                    (7, "99.950004"),
+                    (8, "1"),
+                ]),
+                timezone: Some(chrono_tz::America::New_York),
+                ..Default::default()
+            },
+            risk_free_rate: Decimal::zero(),
+        };
+        let ticker = bars(&[
+            (0, "100"),
+            (1, "102"),
+            (2, "99.96"),
+            (3, "500"),
+            (4, "103.9584"),
+            (7, "99.800064"),
+        ]);
+        let stats = BenchmarkStats::compute(
+            &ticker,
+            chrono_tz::America::New_York,
+            &benchmark,
+            Interval::OneDay,
+        );
+        assert_eq!(stats.benchmark, "SPY");
+        assert_eq!(stats.bars, 5);
+        assert_close(stats.beta, Decimal::from(2));
+        assert_close(stats.correlation, Decimal::one());
+        assert_close(stats.r_squared, Decimal::one());
+        assert_close(stats.alpha, Decimal::zero());
+        // the return difference is the benchmark's own return
+        let m = ["0.01", "-0.01", "0.02", "-0.02"]
+            .iter()
+            .map(|r| Decimal::from_str(r).unwrap())
+            .collect::<Vec<_>>();
+        assert_close(
+            stats.tracking_error,
+            annualized_volatility(&m, Interval::OneDay).unwrap(),
+        );
+        assert_close(stats.up_capture, Decimal::from(2));
+        assert_close(stats.down_capture, Decimal::from(2));
+        assert_eq!(stats.row().len(), BenchmarkStats::columns().len());
+    }
+
+    #[test]
+    fn nothing_in_common() {
+        let benchmark = Benchmark {
+            info: TickerInfo::unknown("SPY"),
+            history: History {
+                quotes: bars(&[(0, "100"), (1, "101")]),
+                ..Default::default()
+            },
+            risk_free_rate: Decimal::zero(),
+        };
+        let stats = BenchmarkStats::compute(
+            &bars(&[(5, "100"), (6, "101")]),
+            Tz::UTC,
+            &benchmark,
+            Interval::OneDay,
+        );
+        assert_eq!(stats.bars, 0);
+        assert_eq!(stats.beta, None);
+        assert!(stats.row()[1..].iter().all(|c| c.is_empty()));
+    }
+}
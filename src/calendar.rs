@@ -0,0 +1,666 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::download_data::YQuote;
+use crate::interval::Interval;
+use crate::metadata::TickerInfo;
+
+// ----------------------------------------------------------------------------- rules
+
+/// How a holiday's date is worked out for a given year.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DateRule {
+    /// month, day
+    Fixed(u32, u32),
+    /// eg 3rd monday of january = NthWeekday(3, Mon, 1)
+    NthWeekday(u32, Weekday, u32),
+    LastWeekday(Weekday, u32),
+    Easter,
+}
+
+/// What happens when a holiday lands on a weekend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Observance {
+    /// it's just lost
+    None,
+    /// saturday -> friday, sunday -> monday, unless that crosses into another year (NYSE)
+    NearestWeekday,
+    /// next weekday that isn't already a holiday (UK bank holidays)
+    Substitute,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rule {
+    date: DateRule,
+    /// days added after the rule's date, eg good friday = easter -2
+    offset: i64,
+    observance: Observance,
+    from: Option<i32>,
+    until: Option<i32>,
+    /// only used by early closes
+    close: Option<NaiveTime>,
+}
+
+impl Rule {
+    fn applies_in(&self, year: i32) -> bool {
+        self.from.is_none_or(|from| year >= from) && self.until.is_none_or(|until| year <= until)
+    }
+
+    /// the date before any weekend observance
+    fn date_in(&self, year: i32) -> Option<NaiveDate> {
+        let date = match self.date {
+            DateRule::Fixed(month, day) => NaiveDate::from_ymd_opt(year, month, day)?,
+            DateRule::NthWeekday(n, weekday, month) => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let shift = (7 + weekday.num_days_from_monday() as i64
+                    - first.weekday().num_days_from_monday() as i64)
+                    % 7;
+                first + Duration::days(shift + 7 * (n as i64 - 1))
+            }
+            DateRule::LastWeekday(weekday, month) => {
+                let (y, m) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                let last = NaiveDate::from_ymd_opt(y, m, 1)?.pred();
+                let shift = (7 + last.weekday().num_days_from_monday() as i64
+                    - weekday.num_days_from_monday() as i64)
+                    % 7;
+                last - Duration::days(shift)
+            }
+            DateRule::Easter => easter_sunday(year),
+        };
+        Some(date + Duration::days(self.offset))
+    }
+}
+
+/// Anonymous gregorian algorithm (Meeus/Jones/Butcher).
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd(year, month as u32, day as u32)
+}
+
+fn parse_weekday(s: &str) -> Result<Weekday, String> {
+    s.parse()
+        .map_err(|_| format!("unknown weekday '{}' - use mon, tue, ...", s))
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str, what: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("bad {} '{}'", what, s))
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(s, "%H:%M").map_err(|_| format!("bad time '{}', use HH:MM", s))
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("bad date '{}', use YYYY-MM-DD", s))
+}
+
+/// "MM-DD", "3:mon:01", "last:mon:05" or "easter"
+fn parse_date_rule(s: &str) -> Result<DateRule, String> {
+    if s == "easter" {
+        return Ok(DateRule::Easter);
+    }
+    let parts: Vec<&str> = s.split(':').collect();
+    match parts.as_slice() {
+        ["last", weekday, month] => Ok(DateRule::LastWeekday(
+            parse_weekday(weekday)?,
+            parse_number(month, "month")?,
+        )),
+        [n, weekday, month] => Ok(DateRule::NthWeekday(
+            parse_number(n, "week number")?,
+            parse_weekday(weekday)?,
+            parse_number(month, "month")?,
+        )),
+        [fixed] => match fixed.split_once('-') {
+            Some((month, day)) => Ok(DateRule::Fixed(
+                parse_number(month, "month")?,
+                parse_number(day, "day")?,
+            )),
+            None => Err(format!("bad date rule '{}'", s)),
+        },
+        _ => Err(format!("bad date rule '{}'", s)),
+    }
+}
+
+/// `<date rule> [+N|-N] [observed|substitute] [from YYYY] [until YYYY] [HH:MM]`
+fn parse_rule(args: &[&str]) -> Result<Rule, String> {
+    let (first, rest) = args.split_first().ok_or("missing date rule")?;
+    let mut rule = Rule {
+        date: parse_date_rule(first)?,
+        offset: 0,
+        observance: Observance::None,
+        from: None,
+        until: None,
+        close: None,
+    };
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match *arg {
+            "observed" => rule.observance = Observance::NearestWeekday,
+            "substitute" => rule.observance = Observance::Substitute,
+            "from" => {
+                rule.from = Some(parse_number(
+                    rest.next().ok_or("from needs a year")?,
+                    "year",
+                )?)
+            }
+            "until" => {
+                rule.until = Some(parse_number(
+                    rest.next().ok_or("until needs a year")?,
+                    "year",
+                )?)
+            }
+            offset if offset.starts_with('+') || offset.starts_with('-') => {
+                rule.offset = parse_number(offset.trim_start_matches('+'), "offset")?
+            }
+            time if time.contains(':') => rule.close = Some(parse_time(time)?),
+            other => return Err(format!("unexpected '{}'", other)),
+        }
+    }
+    Ok(rule)
+}
+
+// ----------------------------------------------------------------------------- calendar
+
+/// One trading day's hours, already in utc.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// local date at the exchange
+    pub date: NaiveDate,
+    pub open: DateTime<Utc>,
+    pub close: DateTime<Utc>,
+    pub early_close: bool,
+}
+
+impl Session {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        at >= self.open && at < self.close
+    }
+}
+
+/// When an exchange trades - built from a rule file, see the ones bundled in calendars/.
+#[derive(Clone, Debug)]
+pub struct TradingCalendar {
+    pub name: String,
+    /// other names the exchange goes by, eg yahoo's exchange codes
+    pub aliases: Vec<String>,
+    pub timezone: Tz,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    weekend: Vec<Weekday>,
+    holidays: Vec<Rule>,
+    early_closes: Vec<Rule>,
+    /// one-off closures
+    closed: BTreeSet<NaiveDate>,
+    /// one-off openings on what the rules say is a holiday
+    opened: BTreeSet<NaiveDate>,
+}
+
+impl TradingCalendar {
+    /// Parses a rule file. Blank lines and anything after # are ignored.
+    pub fn parse(rules: &str) -> Result<Self, String> {
+        let mut calendar = TradingCalendar {
+            name: String::new(),
+            aliases: vec![],
+            timezone: Tz::UTC,
+            open: NaiveTime::from_hms(0, 0, 0),
+            close: NaiveTime::from_hms(23, 59, 59),
+            weekend: vec![Weekday::Sat, Weekday::Sun],
+            holidays: vec![],
+            early_closes: vec![],
+            closed: BTreeSet::new(),
+            opened: BTreeSet::new(),
+        };
+        for (n, line) in rules.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let (directive, args) = match tokens.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+            calendar
+                .apply(directive, args)
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+        if calendar.name.is_empty() {
+            return Err("calendar has no name".into());
+        }
+        Ok(calendar)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let rules = fs::read_to_string(&path)
+            .map_err(|e| format!("can't read {}: {}", path.as_ref().display(), e))?;
+        Self::parse(&rules)
+    }
+
+    fn apply(&mut self, directive: &str, args: &[&str]) -> Result<(), String> {
+        match directive {
+            "name" => self.name = args.join(" "),
+            "alias" => self.aliases.extend(args.iter().map(|a| a.to_string())),
+            "timezone" => {
+                self.timezone = args
+                    .first()
+                    .ok_or("timezone needs a name")?
+                    .parse()
+                    .map_err(|e| format!("bad timezone: {}", e))?
+            }
+            "session" => match args {
+                [open, close] => {
+                    self.open = parse_time(open)?;
+                    self.close = parse_time(close)?;
+                }
+                _ => return Err("session needs an open and a close time".into()),
+            },
+            "weekend" => {
+                self.weekend = args
+                    .iter()
+                    .map(|d| parse_weekday(d))
+                    .collect::<Result<_, _>>()?
+            }
+            "holiday" => self.holidays.push(parse_rule(args)?),
+            "early" => {
+                let rule = parse_rule(args)?;
+                if rule.close.is_none() {
+                    return Err("early close needs a HH:MM close time".into());
+                }
+                self.early_closes.push(rule);
+            }
+            "closed" => {
+                for date in args {
+                    self.closed.insert(parse_date(date)?);
+                }
+            }
+            "open" => {
+                for date in args {
+                    self.opened.insert(parse_date(date)?);
+                }
+            }
+            other => return Err(format!("unknown directive '{}'", other)),
+        }
+        Ok(())
+    }
+
+    fn is_weekend(&self, date: NaiveDate) -> bool {
+        self.weekend.contains(&date.weekday())
+    }
+
+    /// Every rule-based holiday in a year, after weekend observance. Doesn't include the one-off closures.
+    pub fn holidays_in(&self, year: i32) -> BTreeSet<NaiveDate> {
+        let rules: Vec<(Rule, NaiveDate)> = self
+            .holidays
+            .iter()
+            .filter(|r| r.applies_in(year))
+            .filter_map(|r| r.date_in(year).map(|d| (*r, d)))
+            .collect();
+        // weekday holidays first, so the substitutes know which days are already taken
+        let mut holidays: BTreeSet<NaiveDate> = rules
+            .iter()
+            .filter(|(_, d)| !self.is_weekend(*d))
+            .map(|(_, d)| *d)
+            .collect();
+        for (rule, date) in rules.iter().filter(|(_, d)| self.is_weekend(*d)) {
+            let observed = match rule.observance {
+                Observance::None => None,
+                Observance::NearestWeekday => {
+                    let mut observed = *date;
+                    while self.is_weekend(observed) {
+                        observed = match observed.weekday() {
+                            Weekday::Sat => observed.pred(),
+                            _ => observed.succ(),
+                        };
+                    }
+                    Some(observed).filter(|d| d.year() == year)
+                }
+                Observance::Substitute => {
+                    let mut observed = *date;
+                    while self.is_weekend(observed) || holidays.contains(&observed) {
+                        observed = observed.succ();
+                    }
+                    Some(observed)
+                }
+            };
+            holidays.extend(observed);
+        }
+        holidays
+    }
+
+    fn is_trading_day_with(&self, date: NaiveDate, holidays: &BTreeSet<NaiveDate>) -> bool {
+        if self.opened.contains(&date) {
+            return true;
+        }
+        !self.is_weekend(date) && !self.closed.contains(&date) && !holidays.contains(&date)
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        self.is_trading_day_with(date, &self.holidays_in(date.year()))
+    }
+
+    fn session_with(&self, date: NaiveDate, holidays: &BTreeSet<NaiveDate>) -> Option<Session> {
+        if !self.is_trading_day_with(date, holidays) {
+            return None;
+        }
+        let early = self
+            .early_closes
+            .iter()
+            .filter(|r| r.applies_in(date.year()) && r.date_in(date.year()) == Some(date))
+            .filter_map(|r| r.close)
+            .min();
+        let to_utc = |time: NaiveTime| {
+            self.timezone
+                .from_local_datetime(&date.and_time(time))
+                .earliest()
+                .map(|t| t.with_timezone(&Utc))
+        };
+        Some(Session {
+            date,
+            open: to_utc(self.open)?,
+            close: to_utc(early.unwrap_or(self.close))?,
+            early_close: early.is_some(),
+        })
+    }
+
+    /// The session on a local date, None if the exchange is shut.
+    pub fn session(&self, date: NaiveDate) -> Option<Session> {
+        self.session_with(date, &self.holidays_in(date.year()))
+    }
+
+    /// All sessions with local dates in from..=to.
+    pub fn sessions_between(&self, from: NaiveDate, to: NaiveDate) -> Vec<Session> {
+        let mut sessions = vec![];
+        let mut holidays = self.holidays_in(from.year());
+        let mut date = from;
+        while date <= to {
+            if date.ordinal() == 1 {
+                holidays = self.holidays_in(date.year());
+            }
+            sessions.extend(self.session_with(date, &holidays));
+            date = date.succ();
+        }
+        sessions
+    }
+
+    /// The session in progress at `at`, or the next one to open.
+    pub fn next_session(&self, at: DateTime<Utc>) -> Option<Session> {
+        let today = at.with_timezone(&self.timezone).date().naive_local();
+        // no exchange shuts for more than a few weeks - give up after a year so a broken rule file can't hang us
+        self.sessions_between(today, today + Duration::days(366))
+            .into_iter()
+            .find(|s| s.close > at)
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        let today = at.with_timezone(&self.timezone).date().naive_local();
+        self.session(today).is_some_and(|s| s.contains(at))
+    }
+}
+
+// ----------------------------------------------------------------------------- bundled
+
+static BUNDLED: OnceLock<Vec<TradingCalendar>> = OnceLock::new();
+
+/// The calendars shipped with the crate: NYSE (also NASDAQ), LSE and XETRA.
+pub fn bundled() -> &'static [TradingCalendar] {
+    BUNDLED.get_or_init(|| {
+        [
+            include_str!("../calendars/nyse.cal"),
+            include_str!("../calendars/lse.cal"),
+            include_str!("../calendars/xetra.cal"),
+        ]
+        .iter()
+        .map(|rules| TradingCalendar::parse(rules).expect("bundled calendar is broken"))
+        .collect()
+    })
+}
+
+/// Looks a bundled calendar up by name or alias, case insensitive.
+pub fn by_name(name: &str) -> Option<&'static TradingCalendar> {
+    bundled().iter().find(|c| {
+        c.name.eq_ignore_ascii_case(name) || c.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Guesses the exchange from yahoo's ticker suffix - no suffix means a US listing. None for suffixes
+/// we have no calendar for (.T, .HK, .AX, ...) and for fx and futures (EURUSD=X, CL=F).
+pub fn for_ticker(ticker: &str) -> Option<&'static TradingCalendar> {
+    if ticker.contains('=') {
+        return None;
+    }
+    let name = match ticker.rsplit_once('.').map(|(_, s)| s.to_uppercase()) {
+        Some(suffix) if suffix == "L" || suffix == "IL" => "LSE",
+        Some(suffix) if suffix == "DE" || suffix == "F" => "XETRA",
+        Some(_) => return None,
+        None => "NYSE",
+    };
+    by_name(name)
+}
+
+/// Picks the calendar from the vendor's exchange code, falling back to the ticker suffix.
+pub fn for_info(info: &TickerInfo) -> Option<&'static TradingCalendar> {
+    info.exchange
+        .as_deref()
+        .and_then(by_name)
+        .or_else(|| for_ticker(&info.symbol))
+}
+
+// ----------------------------------------------------------------------------- gaps
+
+/// A run of consecutive sessions with no bars.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Gap {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub sessions: usize,
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.from == self.to {
+            write!(f, "{}", self.from)
+        } else {
+            write!(f, "{}..{} ({} sessions)", self.from, self.to, self.sessions)
+        }
+    }
+}
+
+/// Sessions between the first and last bar that have no bar at all - holidays and weekends
+/// aren't gaps. Bars are dated in `timezone`, the zone the data was stamped for.
+/// Only checks session coverage, so weekly / monthly bars never have gaps.
+pub fn find_gaps(
+    calendar: &TradingCalendar,
+    quotes: &[YQuote],
+    timezone: Tz,
+    interval: Interval,
+) -> Vec<Gap> {
+    if matches!(interval, Interval::OneWeek | Interval::OneMonth) {
+        return vec![];
+    }
+    let dates: BTreeSet<NaiveDate> = quotes.iter().map(|q| q.trading_date(timezone)).collect();
+    let (first, last) = match (dates.iter().next(), dates.iter().next_back()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return vec![],
+    };
+
+    let mut gaps: Vec<Gap> = vec![];
+    let mut in_gap = false;
+    for session in calendar.sessions_between(first, last) {
+        if dates.contains(&session.date) {
+            in_gap = false;
+            continue;
+        }
+        match gaps.last_mut() {
+            Some(gap) if in_gap => {
+                gap.to = session.date;
+                gap.sessions += 1;
+            }
+            _ => gaps.push(Gap {
+                from: session.date,
+                to: session.date,
+                sessions: 1,
+            }),
+        }
+        in_gap = true;
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn nyse() -> &'static TradingCalendar {
+        by_name("nyse").unwrap()
+    }
+
+    fn lse() -> &'static TradingCalendar {
+        by_name("LSE").unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn nyse_observes_weekend_holidays() {
+        // july 4th 2021 was a sunday
+        assert!(!nyse().is_trading_day(date(2021, 7, 5)));
+        assert!(nyse().is_trading_day(date(2021, 7, 2)));
+        // christmas 2021 was a saturday
+        assert!(!nyse().is_trading_day(date(2021, 12, 24)));
+        // but new year's 2022 on a saturday isn't made up on new year's eve
+        assert!(nyse().is_trading_day(date(2021, 12, 31)));
+    }
+
+    #[test]
+    fn nyse_juneteenth_only_from_2022() {
+        // the 19th was a saturday in 2021, would have been observed on the friday
+        assert!(nyse().is_trading_day(date(2021, 6, 18)));
+        // a sunday in 2022
+        assert!(!nyse().is_trading_day(date(2022, 6, 20)));
+        assert!(!nyse().is_trading_day(date(2023, 6, 19)));
+    }
+
+    #[test]
+    fn nyse_good_friday() {
+        assert!(!nyse().is_trading_day(date(2021, 4, 2)));
+        assert!(!nyse().is_trading_day(date(2022, 4, 15)));
+        assert!(nyse().is_trading_day(date(2022, 4, 18)));
+    }
+
+    #[test]
+    fn nyse_closes_early_after_thanksgiving() {
+        assert!(!nyse().is_trading_day(date(2021, 11, 25)));
+        let friday = nyse().session(date(2021, 11, 26)).unwrap();
+        assert!(friday.early_close);
+        // 13:00 in new york, EST
+        assert_eq!(friday.open, Utc.ymd(2021, 11, 26).and_hms(14, 30, 0));
+        assert_eq!(friday.close, Utc.ymd(2021, 11, 26).and_hms(18, 0, 0));
+        assert!(nyse().is_open(Utc.ymd(2021, 11, 26).and_hms(17, 59, 0)));
+        assert!(!nyse().is_open(Utc.ymd(2021, 11, 26).and_hms(18, 0, 0)));
+        assert!(!nyse().session(date(2021, 11, 29)).unwrap().early_close);
+    }
+
+    #[test]
+    fn lse_substitute_days() {
+        // christmas on a saturday, boxing day on a sunday - both move to the next free weekday
+        assert!(!lse().is_trading_day(date(2021, 12, 27)));
+        assert!(!lse().is_trading_day(date(2021, 12, 28)));
+        assert!(lse().is_trading_day(date(2021, 12, 29)));
+        let eve = lse().session(date(2021, 12, 24)).unwrap();
+        assert!(eve.early_close);
+        assert_eq!(eve.close, Utc.ymd(2021, 12, 24).and_hms(12, 30, 0));
+    }
+
+    #[test]
+    fn next_session_skips_the_weekend() {
+        let monday = nyse().session(date(2021, 7, 12)).unwrap();
+        // friday after the close, and saturday
+        let friday_evening = Utc.ymd(2021, 7, 9).and_hms(21, 0, 0);
+        assert_eq!(nyse().next_session(friday_evening), Some(monday));
+        assert_eq!(
+            nyse().next_session(Utc.ymd(2021, 7, 10).and_hms(15, 0, 0)),
+            Some(monday)
+        );
+        assert_eq!(monday.open, Utc.ymd(2021, 7, 12).and_hms(13, 30, 0));
+        // during a session it's that one
+        let friday_noon = Utc.ymd(2021, 7, 9).and_hms(16, 0, 0);
+        assert_eq!(
+            nyse().next_session(friday_noon).map(|s| s.date),
+            Some(date(2021, 7, 9))
+        );
+    }
+
+    fn bar(d: u32) -> YQuote {
+        YQuote {
+            timestamp: Utc.ymd(2021, 7, d).and_hms(13, 30, 0),
+            open: Decimal::ONE,
+            high: Decimal::ONE,
+            low: Decimal::ONE,
+            volume: 100,
+            close: Decimal::ONE,
+            adjclose: Decimal::ONE,
+        }
+    }
+
+    #[test]
+    fn gaps_are_missing_sessions_not_holidays() {
+        // the 5th is the july 4th holiday, nothing on the 7th and 8th
+        let quotes: Vec<YQuote> = [1, 2, 6, 9, 12].iter().map(|&d| bar(d)).collect();
+        let gaps = find_gaps(
+            nyse(),
+            &quotes,
+            chrono_tz::America::New_York,
+            Interval::OneDay,
+        );
+        assert_eq!(
+            gaps,
+            vec![Gap {
+                from: date(2021, 7, 7),
+                to: date(2021, 7, 8),
+                sessions: 2
+            }]
+        );
+        assert!(find_gaps(
+            nyse(),
+            &quotes,
+            chrono_tz::America::New_York,
+            Interval::OneWeek
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn calendar_from_the_ticker() {
+        assert_eq!(for_ticker("AAPL").map(|c| c.name.as_str()), Some("NYSE"));
+        assert_eq!(for_ticker("vod.l").map(|c| c.name.as_str()), Some("LSE"));
+        assert_eq!(for_ticker("SAP.DE").map(|c| c.name.as_str()), Some("XETRA"));
+        assert!(for_ticker("7203.T").is_none());
+        assert!(for_ticker("EURUSD=X").is_none());
+        assert_eq!(by_name("nasdaq").map(|c| c.name.as_str()), Some("NYSE"));
+    }
+}
@@ -0,0 +1,612 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::download_data::YQuote;
+use crate::process_data::Data;
+
+/// What to do with a bar that has missing (zero / negative) prices, or that was thrown out as an outlier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingPolicy {
+    Drop,
+    /// take the previous good bar's close
+    ForwardFill,
+    /// straight line between the neighbouring good bars, by timestamp
+    Interpolate,
+}
+
+impl FromStr for MissingPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "drop" => Ok(MissingPolicy::Drop),
+            "ffill" | "forward-fill" => Ok(MissingPolicy::ForwardFill),
+            "interpolate" => Ok(MissingPolicy::Interpolate),
+            _ => Err(format!(
+                "unknown missing data policy '{}' - use drop, ffill or interpolate",
+                s
+            )),
+        }
+    }
+}
+
+/// How to spot a bad tick, based on close-to-close moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutlierFilter {
+    /// move bigger than this fraction, eg 0.5 = 50%
+    PercentJump(Decimal),
+    /// return more than this many standard deviations from the mean return
+    ZScore(f64),
+}
+
+impl FromStr for OutlierFilter {
+    type Err = String;
+
+    /// "jump:0.5" or "zscore:4"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || {
+            format!(
+                "unknown outlier filter '{}' - use jump:<fraction> or zscore:<sigmas>",
+                s
+            )
+        };
+        match s.split_once(':') {
+            Some(("jump", pct)) => Ok(OutlierFilter::PercentJump(
+                Decimal::from_str(pct).map_err(|_| bad())?,
+            )),
+            Some(("zscore", z)) => Ok(OutlierFilter::ZScore(z.parse().map_err(|_| bad())?)),
+            _ => Err(bad()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CleaningPolicy {
+    pub missing: MissingPolicy,
+    pub outliers: Option<OutlierFilter>,
+    /// true = treat outliers like missing bars, false = only report them
+    pub remove_outliers: bool,
+    /// fix bars where low/high don't bracket open/close by widening low/high
+    pub repair_ohlc: bool,
+}
+
+impl Default for CleaningPolicy {
+    fn default() -> Self {
+        Self {
+            missing: MissingPolicy::ForwardFill,
+            outliers: None,
+            remove_outliers: false,
+            repair_ohlc: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FixKind {
+    /// price field was zero / negative
+    Missing {
+        fields: Vec<&'static str>,
+    },
+    /// low > open/close or high < open/close
+    OhlcViolation {
+        repaired: bool,
+    },
+    Outlier {
+        change: Decimal,
+        removed: bool,
+    },
+    Dropped,
+    ForwardFilled,
+    Interpolated,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fix {
+    pub timestamp: DateTime<Utc>,
+    pub kind: FixKind,
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.timestamp.to_rfc3339())?;
+        match &self.kind {
+            FixKind::Missing { fields } => write!(f, "missing {}", fields.join("/")),
+            FixKind::OhlcViolation { repaired: true } => write!(f, "ohlc out of order, repaired"),
+            FixKind::OhlcViolation { repaired: false } => write!(f, "ohlc out of order"),
+            FixKind::Outlier { change, removed } => write!(
+                f,
+                "outlier, close moved {}%{}",
+                (*change * Decimal::from(100)).round_dp(2),
+                if *removed { ", removed" } else { "" }
+            ),
+            FixKind::Dropped => write!(f, "dropped"),
+            FixKind::ForwardFilled => write!(f, "forward-filled"),
+            FixKind::Interpolated => write!(f, "interpolated"),
+        }
+    }
+}
+
+/// Every problem found and fix applied while cleaning one ticker.
+#[derive(Clone, Debug, Default)]
+pub struct QualityReport {
+    pub ticker: String,
+    pub bars_in: usize,
+    pub bars_out: usize,
+    pub fixes: Vec<Fix>,
+}
+
+impl QualityReport {
+    pub fn is_clean(&self) -> bool {
+        self.fixes.is_empty()
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} bars in, {} out, {} fixes",
+            self.ticker,
+            self.bars_in,
+            self.bars_out,
+            self.fixes.len()
+        )?;
+        for fix in &self.fixes {
+            write!(f, "\n  {}", fix)?;
+        }
+        Ok(())
+    }
+}
+
+fn missing_fields(q: &YQuote) -> Vec<&'static str> {
+    let zero = Decimal::zero();
+    [
+        ("open", q.open),
+        ("high", q.high),
+        ("low", q.low),
+        ("close", q.close),
+        ("adjclose", q.adjclose),
+    ]
+    .iter()
+    .filter(|(_, v)| *v <= zero)
+    .map(|(name, _)| *name)
+    .collect()
+}
+
+/// Widens high / low so they bracket open and close. Returns whether anything was out of order.
+fn repair_ohlc(q: &mut YQuote, repair: bool) -> bool {
+    let top = q.open.max(q.close);
+    let bottom = q.open.min(q.close);
+    let broken = q.high < top || q.low > bottom || q.low > q.high;
+    if broken && repair {
+        q.high = q.high.max(top).max(q.low);
+        q.low = q.low.min(bottom).min(q.high);
+    }
+    broken
+}
+
+/// Bars that look like a bad tick: the close-to-close move into the bar trips the filter, and so does the
+/// move back out of it, the other way. A jump that sticks isn't one, and neither is the bar after a spike.
+/// The first and last bars only have the one move, so that's what they're judged on.
+/// Returns (index, move into the bar - or out of it for the first bar).
+fn find_outliers(quotes: &[YQuote], bad: &[bool], filter: OutlierFilter) -> Vec<(usize, Decimal)> {
+    // only compare good bars with each other
+    let good: Vec<usize> = (0..quotes.len()).filter(|i| !bad[*i]).collect();
+    // changes[k] is the move from good[k] to good[k + 1]
+    let changes: Vec<Decimal> = good
+        .windows(2)
+        .map(|w| quotes[w[1]].close / quotes[w[0]].close - Decimal::one())
+        .collect();
+    // which way each move tripped the filter: 1 up, -1 down, 0 not at all
+    let tripped: Vec<i8> = match filter {
+        OutlierFilter::PercentJump(max) => changes
+            .iter()
+            .map(|c| if c.abs() > max { direction(*c) } else { 0 })
+            .collect(),
+        OutlierFilter::ZScore(threshold) => {
+            let returns: Vec<f64> = changes.iter().map(|c| c.to_f64().unwrap_or(0.0)).collect();
+            if returns.len() < 2 {
+                return vec![];
+            }
+            let n = returns.len() as f64;
+            let mean = returns.iter().sum::<f64>() / n;
+            let std = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+            if std == 0.0 {
+                return vec![];
+            }
+            returns
+                .iter()
+                .map(|r| {
+                    let z = (r - mean) / std;
+                    if z.abs() > threshold {
+                        z.signum() as i8
+                    } else {
+                        0
+                    }
+                })
+                .collect()
+        }
+    };
+    let mut outliers = vec![];
+    for (k, &i) in good.iter().enumerate() {
+        let into = k.checked_sub(1);
+        let out_of = (k < changes.len()).then_some(k);
+        let spike = match (into.map(|m| tripped[m]), out_of.map(|m| tripped[m])) {
+            (Some(a), Some(b)) => a != 0 && b == -a,
+            (Some(a), None) | (None, Some(a)) => a != 0,
+            (None, None) => false,
+        };
+        if let (true, Some(m)) = (spike, into.or(out_of)) {
+            outliers.push((i, changes[m]));
+        }
+    }
+    outliers
+}
+
+fn direction(change: Decimal) -> i8 {
+    if change.is_sign_negative() {
+        -1
+    } else {
+        1
+    }
+}
+
+/// Runs the whole cleaning pipeline: missing fields, ohlc invariants, outliers, then fills / drops per the policy.
+/// Expects quotes sorted by timestamp.
+pub fn clean(quotes: &[YQuote], ticker: &str, policy: &CleaningPolicy) -> (Data, QualityReport) {
+    let mut report = QualityReport {
+        ticker: ticker.to_string(),
+        bars_in: quotes.len(),
+        ..QualityReport::default()
+    };
+    let mut quotes = quotes.to_vec();
+    let mut bad = vec![false; quotes.len()];
+
+    for (i, q) in quotes.iter_mut().enumerate() {
+        let fields = missing_fields(q);
+        if !fields.is_empty() {
+            report.fixes.push(Fix {
+                timestamp: q.timestamp,
+                kind: FixKind::Missing { fields },
+            });
+            bad[i] = true;
+            continue;
+        }
+        if repair_ohlc(q, policy.repair_ohlc) {
+            report.fixes.push(Fix {
+                timestamp: q.timestamp,
+                kind: FixKind::OhlcViolation {
+                    repaired: policy.repair_ohlc,
+                },
+            });
+        }
+    }
+
+    if let Some(filter) = policy.outliers {
+        for (i, change) in find_outliers(&quotes, &bad, filter) {
+            report.fixes.push(Fix {
+                timestamp: quotes[i].timestamp,
+                kind: FixKind::Outlier {
+                    change,
+                    removed: policy.remove_outliers,
+                },
+            });
+            bad[i] |= policy.remove_outliers;
+        }
+    }
+
+    let cleaned = fill_bad(&quotes, &bad, policy.missing, &mut report);
+    report.fixes.sort_by_key(|f| f.timestamp);
+    report.bars_out = cleaned.len();
+    (cleaned, report)
+}
+
+fn fill_bad(
+    quotes: &[YQuote],
+    bad: &[bool],
+    policy: MissingPolicy,
+    report: &mut QualityReport,
+) -> Data {
+    // the nearest good bar after each one, filled in from the back
+    let mut next_good = vec![None; quotes.len()];
+    for i in (0..quotes.len().saturating_sub(1)).rev() {
+        next_good[i] = if bad[i + 1] {
+            next_good[i + 1]
+        } else {
+            Some(i + 1)
+        };
+    }
+    let mut prev_good: Option<usize> = None;
+    let mut out = Vec::with_capacity(quotes.len());
+    for (i, q) in quotes.iter().enumerate() {
+        if !bad[i] {
+            out.push(q.clone());
+            prev_good = Some(i);
+            continue;
+        }
+        let prev = prev_good.map(|p| &quotes[p]);
+        let next = next_good[i].map(|n| &quotes[n]);
+        let filled = match (policy, prev, next) {
+            // nothing after it to interpolate towards - carry the last good bar forward instead
+            (MissingPolicy::ForwardFill, Some(p), _)
+            | (MissingPolicy::Interpolate, Some(p), None) => {
+                report.fixes.push(Fix {
+                    timestamp: q.timestamp,
+                    kind: FixKind::ForwardFilled,
+                });
+                Some(YQuote {
+                    timestamp: q.timestamp,
+                    open: p.close,
+                    high: p.close,
+                    low: p.close,
+                    volume: 0,
+                    close: p.close,
+                    adjclose: p.adjclose,
+                })
+            }
+            (MissingPolicy::Interpolate, Some(p), Some(n)) => {
+                report.fixes.push(Fix {
+                    timestamp: q.timestamp,
+                    kind: FixKind::Interpolated,
+                });
+                let w = Decimal::from((q.timestamp - p.timestamp).num_seconds())
+                    / Decimal::from((n.timestamp - p.timestamp).num_seconds().max(1));
+                let lerp = |a: Decimal, b: Decimal| a + (b - a) * w;
+                Some(YQuote {
+                    timestamp: q.timestamp,
+                    open: lerp(p.open, n.open),
+                    high: lerp(p.high, n.high),
+                    low: lerp(p.low, n.low),
+                    volume: q.volume,
+                    close: lerp(p.close, n.close),
+                    adjclose: lerp(p.adjclose, n.adjclose),
+                })
+            }
+            // nothing to fill from (or dropping anyway)
+            _ => None,
+        };
+        match filled {
+            Some(f) => out.push(f),
+            None => report.fixes.push(Fix {
+                timestamp: q.timestamp,
+                kind: FixKind::Dropped,
+            }),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// 1 = 2021-01-01, carrying on into February
+    fn day(d: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 1, 1).and_hms(21, 0, 0) + chrono::Duration::days(d as i64 - 1)
+    }
+
+    /// one bar a day from the 1st, open/high/low at the close
+    fn bars(closes: &[i64]) -> Data {
+        closes
+            .iter()
+            .zip(1..)
+            .map(|(&close, d)| {
+                let close = Decimal::from(close);
+                YQuote {
+                    timestamp: day(d),
+                    open: close,
+                    high: close,
+                    low: close,
+                    volume: 100,
+                    close,
+                    adjclose: close,
+                }
+            })
+            .collect()
+    }
+
+    fn closes(quotes: &[YQuote]) -> Vec<Decimal> {
+        quotes.iter().map(|q| q.close).collect()
+    }
+
+    fn decimals(values: &[i64]) -> Vec<Decimal> {
+        values.iter().map(|&v| Decimal::from(v)).collect()
+    }
+
+    fn kinds(report: &QualityReport) -> Vec<(u32, FixKind)> {
+        use chrono::Datelike;
+        report
+            .fixes
+            .iter()
+            .map(|f| (f.timestamp.day(), f.kind.clone()))
+            .collect()
+    }
+
+    fn policy(missing: MissingPolicy) -> CleaningPolicy {
+        CleaningPolicy {
+            missing,
+            ..CleaningPolicy::default()
+        }
+    }
+
+    #[test]
+    fn drop_missing() {
+        let mut quotes = bars(&[10, 0, 14]);
+        quotes[1].adjclose = Decimal::from(12);
+        let (cleaned, report) = clean(&quotes, "TEST", &policy(MissingPolicy::Drop));
+        assert_eq!(closes(&cleaned), decimals(&[10, 14]));
+        assert_eq!(
+            kinds(&report),
+            vec![
+                (
+                    2,
+                    FixKind::Missing {
+                        fields: vec!["open", "high", "low", "close"]
+                    }
+                ),
+                (2, FixKind::Dropped)
+            ]
+        );
+        assert_eq!((report.bars_in, report.bars_out), (3, 2));
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn forward_fill_missing() {
+        let (cleaned, report) = clean(
+            &bars(&[0, 10, 0, 0, 14]),
+            "TEST",
+            &policy(MissingPolicy::ForwardFill),
+        );
+        // nothing before the first bar to fill it from
+        assert_eq!(closes(&cleaned), decimals(&[10, 10, 10, 14]));
+        assert_eq!(cleaned[1].volume, 0);
+        assert_eq!(cleaned[1].timestamp, day(3));
+        let fixes = kinds(&report);
+        assert!(fixes.contains(&(1, FixKind::Dropped)));
+        assert!(fixes.contains(&(3, FixKind::ForwardFilled)));
+        assert!(fixes.contains(&(4, FixKind::ForwardFilled)));
+    }
+
+    #[test]
+    fn interpolate_missing() {
+        let (cleaned, report) = clean(
+            &bars(&[10, 0, 0, 16, 0]),
+            "TEST",
+            &policy(MissingPolicy::Interpolate),
+        );
+        // by time between the good neighbours, and carried forward past the last one
+        assert_eq!(closes(&cleaned), decimals(&[10, 12, 14, 16, 16]));
+        let fixes = kinds(&report);
+        assert!(fixes.contains(&(2, FixKind::Interpolated)));
+        assert!(fixes.contains(&(3, FixKind::Interpolated)));
+        assert!(fixes.contains(&(5, FixKind::ForwardFilled)));
+        assert_eq!(report.bars_out, 5);
+    }
+
+    #[test]
+    fn ohlc_invariants() {
+        let mut quotes = bars(&[10, 10]);
+        quotes[0].high = Decimal::from(9);
+        quotes[1].low = Decimal::from(11);
+        let (repaired, report) = clean(&quotes, "TEST", &CleaningPolicy::default());
+        assert_eq!(repaired[0].high, Decimal::from(10));
+        assert_eq!(repaired[1].low, Decimal::from(10));
+        assert_eq!(
+            kinds(&report),
+            vec![
+                (1, FixKind::OhlcViolation { repaired: true }),
+                (2, FixKind::OhlcViolation { repaired: true })
+            ]
+        );
+
+        let report_only = CleaningPolicy {
+            repair_ohlc: false,
+            ..CleaningPolicy::default()
+        };
+        let (untouched, report) = clean(&quotes, "TEST", &report_only);
+        assert_eq!(untouched, quotes);
+        assert_eq!(
+            report.fixes[0].kind,
+            FixKind::OhlcViolation { repaired: false }
+        );
+    }
+
+    fn jump(remove: bool) -> CleaningPolicy {
+        CleaningPolicy {
+            outliers: Some(OutlierFilter::PercentJump(Decimal::new(5, 1))),
+            remove_outliers: remove,
+            ..CleaningPolicy::default()
+        }
+    }
+
+    #[test]
+    fn a_spike_is_an_outlier_but_the_bar_after_it_isnt() {
+        let (cleaned, report) = clean(&bars(&[100, 100, 300, 100, 102]), "TEST", &jump(false));
+        assert_eq!(
+            kinds(&report),
+            vec![(
+                3,
+                FixKind::Outlier {
+                    change: Decimal::from(2),
+                    removed: false
+                }
+            )]
+        );
+        // only reported
+        assert_eq!(cleaned.len(), 5);
+
+        let (cleaned, report) = clean(&bars(&[100, 100, 300, 100, 102]), "TEST", &jump(true));
+        assert_eq!(closes(&cleaned), decimals(&[100, 100, 100, 100, 102]));
+        assert_eq!(report.fixes.len(), 2);
+        assert_eq!(report.fixes[1].kind, FixKind::ForwardFilled);
+    }
+
+    #[test]
+    fn a_jump_that_sticks_isnt_an_outlier() {
+        let (_, report) = clean(&bars(&[100, 100, 300, 301, 302]), "TEST", &jump(true));
+        assert!(report.is_clean());
+        // unless it's the last bar, with nothing after it to go by
+        let (_, report) = clean(&bars(&[100, 100, 100, 300]), "TEST", &jump(false));
+        assert_eq!(kinds(&report).len(), 1);
+        assert_eq!(kinds(&report)[0].0, 4);
+    }
+
+    #[test]
+    fn zscore_spike() {
+        let mut closes = vec![];
+        for i in 0..60 {
+            closes.push(100 + i % 2);
+        }
+        closes[30] = 150;
+        let policy = CleaningPolicy {
+            outliers: Some(OutlierFilter::ZScore(3.0)),
+            remove_outliers: true,
+            ..CleaningPolicy::default()
+        };
+        let (cleaned, report) = clean(&bars(&closes), "TEST", &policy);
+        let flagged: Vec<u32> = kinds(&report)
+            .into_iter()
+            .filter(|(_, k)| matches!(k, FixKind::Outlier { .. }))
+            .map(|(d, _)| d)
+            .collect();
+        assert_eq!(flagged, vec![31]);
+        assert_eq!(cleaned[30].close, Decimal::from(101));
+        // a flat series has no spread to measure against
+        let (_, report) = clean(&bars(&[100; 10]), "TEST", &policy);
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn report_lists_every_fix_in_time_order() {
+        let mut quotes = bars(&[10, 0, 12]);
+        quotes[0].low = Decimal::from(11);
+        let (_, report) = clean(&quotes, "TEST", &CleaningPolicy::default());
+        assert_eq!(
+            report.to_string(),
+            "TEST: 3 bars in, 3 out, 3 fixes\n  \
+             2021-01-01T21:00:00+00:00 ohlc out of order, repaired\n  \
+             2021-01-02T21:00:00+00:00 missing open/high/low/close/adjclose\n  \
+             2021-01-02T21:00:00+00:00 forward-filled"
+        );
+    }
+
+    #[test]
+    fn parses_the_cli_forms() {
+        assert_eq!("ffill".parse(), Ok(MissingPolicy::ForwardFill));
+        assert_eq!("Drop".parse(), Ok(MissingPolicy::Drop));
+        assert!("zero".parse::<MissingPolicy>().is_err());
+        assert_eq!(
+            "jump:0.5".parse(),
+            Ok(OutlierFilter::PercentJump(Decimal::new(5, 1)))
+        );
+        assert_eq!("zscore:4".parse(), Ok(OutlierFilter::ZScore(4.0)));
+        assert!("jump".parse::<OutlierFilter>().is_err());
+    }
+}
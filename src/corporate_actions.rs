@@ -0,0 +1,93 @@
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+/// Cash dividend, dated by its ex-date.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dividend {
+    pub ex_date: DateTime<Utc>,
+    pub amount: Decimal,
+}
+
+/// Share split, eg a 4:1 split has numerator 4 and denominator 1 - every old share becomes 4.
+/// Reverse splits just have numerator < denominator.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Split {
+    pub date: DateTime<Utc>,
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl Split {
+    /// new shares per old share
+    pub fn ratio(&self) -> Decimal {
+        Decimal::from(self.numerator) / Decimal::from(self.denominator.max(1))
+    }
+}
+
+/// Everything that happened to the share count or paid out cash over a period, sorted by date.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CorporateActions {
+    pub dividends: Vec<Dividend>,
+    pub splits: Vec<Split>,
+}
+
+impl CorporateActions {
+    pub fn is_empty(&self) -> bool {
+        self.dividends.is_empty() && self.splits.is_empty()
+    }
+
+    /// adds `other`'s events, keeping the lists sorted and free of duplicates
+    pub fn merge(&mut self, other: CorporateActions) {
+        self.dividends.extend(other.dividends);
+        self.dividends.sort();
+        self.dividends.dedup();
+        self.splits.extend(other.splits);
+        self.splits.sort();
+        self.splits.dedup();
+    }
+
+    /// only the events dated within from..=to
+    pub fn between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> CorporateActions {
+        CorporateActions {
+            dividends: self
+                .dividends
+                .iter()
+                .filter(|d| d.ex_date >= from && d.ex_date <= to)
+                .cloned()
+                .collect(),
+            splits: self
+                .splits
+                .iter()
+                .filter(|s| s.date >= from && s.date <= to)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// cash paid per share over the whole period
+    pub fn total_dividends(&self) -> Decimal {
+        self.dividends.iter().map(|d| d.amount).sum()
+    }
+}
+
+impl From<&yahoo_finance_api::Dividend> for Dividend {
+    fn from(d: &yahoo_finance_api::Dividend) -> Self {
+        Self {
+            ex_date: Utc.timestamp(d.date as i64, 0),
+            amount: Decimal::from_f64(d.amount)
+                .map(|a| a.round_dp(6).normalize())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&yahoo_finance_api::Split> for Split {
+    fn from(s: &yahoo_finance_api::Split) -> Self {
+        Self {
+            date: Utc.timestamp(s.date as i64, 0),
+            numerator: s.numerator,
+            denominator: s.denominator,
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::io;
+
+use chrono::NaiveDateTime;
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+
+use crate::price_frame::PriceFrame;
+use crate::risk::{correlation, covariance, simple_returns};
+use crate::streaming::PriceField;
+
+/// Returns of two tickers' adjcloses over the rows both have - (key of the later bar of each return, a, b).
+pub fn paired_returns(
+    frame: &PriceFrame,
+    a: &str,
+    b: &str,
+) -> (Vec<NaiveDateTime>, Vec<Decimal>, Vec<Decimal>) {
+    let joined = frame.pair(a, b, PriceField::AdjClose).unwrap_or_default();
+    let keys = joined.iter().skip(1).map(|(k, _, _)| *k).collect();
+    let x = simple_returns(&joined.iter().map(|(_, x, _)| *x).collect::<Vec<_>>());
+    let y = simple_returns(&joined.iter().map(|(_, _, y)| *y).collect::<Vec<_>>());
+    (keys, x, y)
+}
+
+/// Correlation over each window of n paired returns, keyed by the window's last bar.
+pub fn rolling_correlation(
+    n: usize,
+    frame: &PriceFrame,
+    a: &str,
+    b: &str,
+) -> Vec<(NaiveDateTime, Option<Decimal>)> {
+    let (keys, x, y) = paired_returns(frame, a, b);
+    if n < 2 || n > keys.len() {
+        return vec![];
+    }
+    x.windows(n)
+        .zip(y.windows(n))
+        .zip(&keys[n - 1..])
+        .map(|((x, y), key)| (*key, correlation(x, y)))
+        .collect()
+}
+
+/// Pairwise return correlations and covariances across a set of tickers. Every pair uses the bars both
+/// of them have, so one ticker's missing day doesn't cost the rest of the matrix anything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CorrelationMatrix {
+    pub symbols: Vec<String>,
+    /// None where a pair has too few common bars or one side never moves
+    pub correlation: Vec<Vec<Option<Decimal>>>,
+    pub covariance: Vec<Vec<Option<Decimal>>>,
+    /// returns each pair had in common
+    pub observations: Vec<Vec<usize>>,
+}
+
+impl CorrelationMatrix {
+    /// across every ticker in the frame, holes and all
+    pub fn compute(frame: &PriceFrame) -> Self {
+        let symbols = frame.symbols();
+        let n = symbols.len();
+        let mut matrix = Self {
+            symbols: symbols.to_vec(),
+            correlation: vec![vec![None; n]; n],
+            covariance: vec![vec![None; n]; n],
+            observations: vec![vec![0; n]; n],
+        };
+        for i in 0..n {
+            for j in i..n {
+                let (_, x, y) = paired_returns(frame, &symbols[i], &symbols[j]);
+                let corr = if i == j && correlation(&x, &y).is_some() {
+                    // exactly 1, not 0.99999... off the decimal sqrt
+                    Some(Decimal::one())
+                } else {
+                    correlation(&x, &y)
+                };
+                let cov = covariance(&x, &y);
+                for (a, b) in [(i, j), (j, i)] {
+                    matrix.correlation[a][b] = corr;
+                    matrix.covariance[a][b] = cov;
+                    matrix.observations[a][b] = x.len();
+                }
+            }
+        }
+        matrix
+    }
+
+    /// one of the matrices as csv, symbols across the top and down the side
+    pub fn write_csv<W: io::Write>(
+        &self,
+        values: &[Vec<Option<Decimal>>],
+        dp: u32,
+        w: W,
+    ) -> Result<(), csv::Error> {
+        let mut wtr = csv::Writer::from_writer(w);
+        let mut header = vec![String::new()];
+        header.extend(self.symbols.iter().cloned());
+        wtr.write_record(&header)?;
+        for (symbol, row) in self.symbols.iter().zip(values) {
+            let mut record = vec![symbol.clone()];
+            record.extend(
+                row.iter()
+                    .map(|v| v.map_or(String::new(), |v| v.round_dp(dp).to_string())),
+            );
+            wtr.write_record(&record)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let matrix = |values: &[Vec<Option<Decimal>>]| -> Value {
+            values
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|v| v.and_then(|v| v.to_f64()).map_or(Value::Null, Value::from))
+                        .collect::<Vec<Value>>()
+                })
+                .collect::<Vec<Vec<Value>>>()
+                .into()
+        };
+        json!({
+            "symbols": self.symbols,
+            "correlation": matrix(&self.correlation),
+            "covariance": matrix(&self.covariance),
+            "observations": self.observations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    use super::*;
+    use crate::download_data::YQuote;
+    use crate::interval::Interval;
+    use crate::price_frame::Join;
+
+    /// daily adjcloses from monday the 4th of january, None = no bar that day
+    fn ticker(symbol: &str, prices: &[Option<i64>]) -> (String, Vec<YQuote>, Tz) {
+        let quotes = prices
+            .iter()
+            .enumerate()
+            .filter_map(|(i, price)| {
+                let price = Decimal::from((*price)?);
+                Some(YQuote {
+                    timestamp: Utc.ymd(2021, 1, 4).and_hms(21, 0, 0) + Duration::days(i as i64),
+                    open: price,
+                    high: price,
+                    low: price,
+                    volume: 100,
+                    close: price,
+                    adjclose: price,
+                })
+            })
+            .collect();
+        (symbol.to_string(), quotes, Tz::UTC)
+    }
+
+    // B is A one for one, C misses the 6th, FLAT never moves
+    fn frame() -> PriceFrame {
+        PriceFrame::join(
+            Interval::OneDay,
+            Join::Outer,
+            vec![
+                ticker("A", &[Some(100), Some(110), Some(99), Some(104), Some(120)]),
+                ticker(
+                    "B",
+                    &[Some(200), Some(220), Some(198), Some(208), Some(240)],
+                ),
+                ticker("C", &[Some(50), Some(60), None, Some(55), Some(58)]),
+                ticker("FLAT", &[Some(10); 5]),
+            ],
+        )
+    }
+
+    fn returns(prices: &[i64]) -> Vec<Decimal> {
+        simple_returns(&prices.iter().map(|&p| Decimal::from(p)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn each_pair_uses_the_bars_both_have() {
+        let matrix = CorrelationMatrix::compute(&frame());
+        assert_eq!(matrix.symbols, vec!["A", "B", "C", "FLAT"]);
+        // A and B have all 5 bars, anything with C only the 4 C has
+        assert_eq!(matrix.observations[0][1], 4);
+        assert_eq!(matrix.observations[0][2], 3);
+        assert_eq!(matrix.observations[2][0], 3);
+        assert_eq!(matrix.observations[2][2], 3);
+
+        assert_eq!(matrix.correlation[0][0], Some(Decimal::one()));
+        let ab = matrix.correlation[0][1].unwrap();
+        assert!((ab - Decimal::one()).abs() < Decimal::new(1, 12));
+        // A's move over the gap is a two bar return, from 110 to 104
+        let expected_ac = correlation(&returns(&[100, 110, 104, 120]), &returns(&[50, 60, 55, 58]));
+        assert_eq!(matrix.correlation[0][2], expected_ac);
+        assert_eq!(matrix.correlation[2][0], expected_ac);
+        assert_eq!(
+            matrix.covariance[0][2],
+            covariance(&returns(&[100, 110, 104, 120]), &returns(&[50, 60, 55, 58]))
+        );
+        // a series that never moves has nothing to correlate
+        assert_eq!(matrix.correlation[0][3], None);
+        assert_eq!(matrix.correlation[3][3], None);
+        assert_eq!(matrix.covariance[0][3], Some(Decimal::zero()));
+    }
+
+    #[test]
+    fn rolling_windows_are_keyed_by_their_last_bar() {
+        let day = |d: u32| NaiveDate::from_ymd(2021, 1, d).and_hms(0, 0, 0);
+        // returns end on the 5th, 7th and 8th - the 6th is missing from C
+        let (keys, _, _) = paired_returns(&frame(), "A", "C");
+        assert_eq!(keys, vec![day(5), day(7), day(8)]);
+        let rolling = rolling_correlation(2, &frame(), "A", "C");
+        let keys: Vec<NaiveDateTime> = rolling.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![day(7), day(8)]);
+        // two returns always line up perfectly, one way or the other
+        for (_, corr) in &rolling {
+            assert_eq!(corr.map(|c| c.abs().round_dp(9)), Some(Decimal::one()));
+        }
+        assert_eq!(rolling_correlation(2, &frame(), "A", "B").len(), 3);
+        assert!(rolling_correlation(4, &frame(), "A", "C").is_empty());
+        assert!(rolling_correlation(2, &frame(), "A", "NOPE").is_empty());
+    }
+
+    #[test]
+    fn csv_and_json() {
+        let matrix = CorrelationMatrix::compute(&frame());
+        let mut csv = vec![];
+        matrix.write_csv(&matrix.correlation, 2, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], ",A,B,C,FLAT");
+        assert!(lines[1].starts_with("A,1,1,"));
+        assert!(lines[1].ends_with(','));
+        let json = matrix.to_json();
+        assert_eq!(json["observations"][0][2], 3);
+        assert!(json["correlation"][0][3].is_null());
+    }
+}
@@ -0,0 +1,493 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::corporate_actions::CorporateActions;
+use crate::download_data::{FetchError, History, QuoteProvider, YQuote};
+use crate::interval::Interval;
+use crate::metadata::{SearchResult, TickerInfo};
+use crate::process_data::Data;
+
+/// Which header in the file holds which field. Header matching is case-insensitive.
+#[derive(Clone, Debug)]
+pub struct ColumnMapping {
+    pub date: String,
+    /// only used when reading a single multi-ticker file
+    pub symbol: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    /// falls back to close if the file doesn't have it
+    pub adjclose: String,
+    /// falls back to 0 if the file doesn't have it
+    pub volume: String,
+}
+
+/// defaults match the layout of a yahoo csv export
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            date: "Date".into(),
+            symbol: "Symbol".into(),
+            open: "Open".into(),
+            high: "High".into(),
+            low: "Low".into(),
+            close: "Close".into(),
+            adjclose: "Adj Close".into(),
+            volume: "Volume".into(),
+        }
+    }
+}
+
+/// Overrides on top of the default mapping, eg "date=Timestamp,close=Last,adjclose=Last"
+impl FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mapping = Self::default();
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (field, header) = match pair.split_once('=') {
+                Some((f, h)) => (f.trim().to_lowercase(), h.trim().to_string()),
+                None => return Err(format!("expected field=header, got '{}'", pair)),
+            };
+            match field.as_str() {
+                "date" => mapping.date = header,
+                "symbol" => mapping.symbol = header,
+                "open" => mapping.open = header,
+                "high" => mapping.high = header,
+                "low" => mapping.low = header,
+                "close" => mapping.close = header,
+                "adjclose" => mapping.adjclose = header,
+                "volume" => mapping.volume = header,
+                _ => return Err(format!("unknown csv field '{}'", field)),
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DecimalFormat {
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+}
+
+impl Default for DecimalFormat {
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            thousands_separator: None,
+        }
+    }
+}
+
+impl DecimalFormat {
+    pub fn parse(&self, raw: &str) -> Result<Decimal, String> {
+        let mut cleaned: String = raw
+            .trim()
+            .chars()
+            .filter(|c| Some(*c) != self.thousands_separator)
+            .collect();
+        if self.decimal_separator != '.' {
+            cleaned = cleaned.replace(self.decimal_separator, ".");
+        }
+        Decimal::from_str(&cleaned)
+            .or_else(|_| Decimal::from_scientific(&cleaned))
+            .map_err(|_| format!("can't parse '{}' as a decimal", raw))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CsvConfig {
+    pub columns: ColumnMapping,
+    /// chrono format string (date only or date + time), or "unix" for epoch seconds
+    pub date_format: String,
+    pub decimal: DecimalFormat,
+    pub delimiter: u8,
+    /// zone the file's dates / times are in - exports are usually in the exchange's local time
+    pub timezone: Tz,
+    /// what the prices are quoted in, the files don't say
+    pub currency: Option<String>,
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        Self {
+            columns: ColumnMapping::default(),
+            date_format: "%Y-%m-%d".into(),
+            decimal: DecimalFormat::default(),
+            delimiter: b',',
+            timezone: Tz::UTC,
+            currency: None,
+        }
+    }
+}
+
+impl CsvConfig {
+    /// times are read in `timezone`, dates without a time component as midnight there
+    pub fn parse_timestamp(&self, raw: &str) -> Result<DateTime<Utc>, String> {
+        let raw = raw.trim();
+        if self.date_format == "unix" {
+            return raw
+                .parse()
+                .map(|secs| Utc.timestamp(secs, 0))
+                .map_err(|_| format!("can't parse '{}' as epoch seconds", raw));
+        }
+        let local = NaiveDateTime::parse_from_str(raw, &self.date_format)
+            .or_else(|_| {
+                NaiveDate::parse_from_str(raw, &self.date_format).map(|d| d.and_hms(0, 0, 0))
+            })
+            .map_err(|_| format!("can't parse '{}' with format '{}'", raw, self.date_format))?;
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(|| format!("'{}' doesn't exist in {}", raw, self.timezone.name()))
+    }
+}
+
+/// Reads vendor-exported OHLCV files from disk.
+/// `path` is either a directory with one `<TICKER>.csv` per ticker, or a single file with a symbol column.
+pub struct CsvProvider {
+    path: PathBuf,
+    config: CsvConfig,
+}
+
+impl CsvProvider {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self::with_config(path, CsvConfig::default())
+    }
+
+    pub fn with_config<P: Into<PathBuf>>(path: P, config: CsvConfig) -> Self {
+        Self {
+            path: path.into(),
+            config,
+        }
+    }
+
+    fn ticker_file(&self, ticker: &str) -> Option<PathBuf> {
+        [
+            ticker.to_string(),
+            ticker.to_uppercase(),
+            ticker.to_lowercase(),
+        ]
+        .iter()
+        .map(|t| self.path.join(format!("{}.csv", t)))
+        .find(|p| p.is_file())
+    }
+
+    /// reads every row of `file`, keeping only rows for `ticker` if the file has a symbol column
+    pub fn read_quotes(&self, file: &Path, ticker: &str) -> Result<Data, FetchError> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(self.config.delimiter)
+            .trim(csv::Trim::All)
+            .from_path(file)
+            .map_err(|e| FetchError::NotFound(format!("{}: {}", file.display(), e)))?;
+
+        let headers = rdr.headers().map_err(malformed)?.clone();
+        let find = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let require = |name: &str| {
+            find(name).ok_or_else(|| {
+                FetchError::Malformed(format!("column '{}' not found in {}", name, file.display()))
+            })
+        };
+        let cols = &self.config.columns;
+        let date = require(&cols.date)?;
+        let open = require(&cols.open)?;
+        let high = require(&cols.high)?;
+        let low = require(&cols.low)?;
+        let close = require(&cols.close)?;
+        let adjclose = find(&cols.adjclose).unwrap_or(close);
+        let volume = find(&cols.volume);
+        let symbol = find(&cols.symbol);
+
+        let mut quotes = Vec::new();
+        for (i, record) in rdr.records().enumerate() {
+            let record = record.map_err(malformed)?;
+            if let Some(s) = symbol {
+                if !record[s].eq_ignore_ascii_case(ticker) {
+                    continue;
+                }
+            }
+            // header is line 1
+            let at_line = |e: String| {
+                FetchError::Malformed(format!("{} line {}: {}", file.display(), i + 2, e))
+            };
+            let decimal = |idx: usize| self.config.decimal.parse(&record[idx]).map_err(at_line);
+            quotes.push(YQuote {
+                timestamp: self
+                    .config
+                    .parse_timestamp(&record[date])
+                    .map_err(at_line)?,
+                open: decimal(open)?,
+                high: decimal(high)?,
+                low: decimal(low)?,
+                volume: match volume {
+                    Some(v) => decimal(v)?.trunc().to_u64().unwrap_or(0),
+                    None => 0,
+                },
+                close: decimal(close)?,
+                adjclose: decimal(adjclose)?,
+            });
+        }
+        Ok(quotes)
+    }
+}
+
+fn malformed(e: csv::Error) -> FetchError {
+    FetchError::Malformed(e.to_string())
+}
+
+#[async_trait]
+impl QuoteProvider for CsvProvider {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        _interval: Interval,
+    ) -> Result<History, FetchError> {
+        let file = if self.path.is_dir() {
+            self.ticker_file(ticker).ok_or_else(|| {
+                FetchError::NotFound(format!(
+                    "no csv file for {} in {}",
+                    ticker,
+                    self.path.display()
+                ))
+            })?
+        } else {
+            self.path.clone()
+        };
+        // vendor price exports don't carry dividends / splits
+        Ok(History {
+            quotes: self
+                .read_quotes(&file, ticker)?
+                .into_iter()
+                .filter(|q| q.timestamp >= from && q.timestamp <= to)
+                .collect(),
+            actions: CorporateActions::default(),
+            timezone: Some(self.config.timezone),
+        })
+    }
+
+    /// csv exports carry no metadata, so this only checks the ticker has any rows
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        let known = if self.path.is_dir() {
+            self.ticker_file(ticker).is_some()
+        } else {
+            !self.read_quotes(&self.path, ticker)?.is_empty()
+        };
+        if !known {
+            return Err(FetchError::NotFound(format!(
+                "{} isn't in {}",
+                ticker,
+                self.path.display()
+            )));
+        }
+        Ok(TickerInfo {
+            timezone: Some(self.config.timezone),
+            currency: self.config.currency.clone(),
+            ..TickerInfo::unknown(ticker)
+        })
+    }
+
+    /// file names containing the query, for a directory of per-ticker files
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        let dir = fs::read_dir(&self.path).map_err(|e| {
+            FetchError::InvalidRequest(format!("can't search {}: {}", self.path.display(), e))
+        })?;
+        let query = query.to_uppercase();
+        let mut found: Vec<SearchResult> = dir
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().to_uppercase()))
+            .filter(|symbol| symbol.contains(&query))
+            .map(|symbol| SearchResult {
+                symbol,
+                name: String::new(),
+                exchange: String::new(),
+                instrument_type: String::new(),
+            })
+            .collect();
+        found.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn file(contents: &str) -> tempfile::NamedTempFile {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        f.write_all(contents.as_bytes()).unwrap();
+        f
+    }
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn column_overrides() {
+        let mapping: ColumnMapping = "date=Timestamp, close=Last,ADJCLOSE=Last".parse().unwrap();
+        assert_eq!(mapping.date, "Timestamp");
+        assert_eq!(mapping.close, "Last");
+        assert_eq!(mapping.adjclose, "Last");
+        // untouched ones keep the yahoo names
+        assert_eq!(mapping.open, "Open");
+        assert!("close".parse::<ColumnMapping>().is_err());
+        assert!("bid=Bid".parse::<ColumnMapping>().is_err());
+        assert!("".parse::<ColumnMapping>().is_ok());
+    }
+
+    #[test]
+    fn decimal_comma() {
+        let european = DecimalFormat {
+            decimal_separator: ',',
+            thousands_separator: Some('.'),
+        };
+        assert_eq!(european.parse(" 1.234,56 ").unwrap(), dec("1234.56"));
+        assert_eq!(european.parse("0,5").unwrap(), dec("0.5"));
+        assert!(european.parse("abc").is_err());
+        let plain = DecimalFormat::default();
+        assert_eq!(plain.parse("1234.56").unwrap(), dec("1234.56"));
+        assert_eq!(plain.parse("1.5e2").unwrap(), dec("150"));
+    }
+
+    #[test]
+    fn dates_are_read_in_the_configured_zone() {
+        let config = CsvConfig {
+            timezone: chrono_tz::America::New_York,
+            ..CsvConfig::default()
+        };
+        // midnight in new york is 5am utc in winter
+        assert_eq!(
+            config.parse_timestamp("2021-01-04").unwrap(),
+            Utc.ymd(2021, 1, 4).and_hms(5, 0, 0)
+        );
+        let config = CsvConfig {
+            date_format: "%Y-%m-%d %H:%M".into(),
+            ..config
+        };
+        assert_eq!(
+            config.parse_timestamp("2021-07-01 09:30").unwrap(),
+            Utc.ymd(2021, 7, 1).and_hms(13, 30, 0)
+        );
+        // clocks go forward at 2am
+        assert!(config.parse_timestamp("2021-03-14 02:30").is_err());
+        let unix = CsvConfig {
+            date_format: "unix".into(),
+            ..CsvConfig::default()
+        };
+        assert_eq!(
+            unix.parse_timestamp("1609459200").unwrap(),
+            Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn multi_ticker_file() {
+        let f = file(
+            "Date,Symbol,Open,High,Low,Close,Volume\n\
+             2021-01-04,AAA,1,2,0.5,1.5,100\n\
+             2021-01-04,BBB,10,20,5,15,200\n\
+             2021-01-05,aaa,1.5,2.5,1,2,300\n",
+        );
+        let provider = CsvProvider::new(f.path());
+        let quotes = provider.read_quotes(f.path(), "AAA").unwrap();
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[1].close, dec("2"));
+        // no adj close column - falls back to close
+        assert_eq!(quotes[1].adjclose, dec("2"));
+        assert_eq!(quotes[1].volume, 300);
+        assert_eq!(provider.read_quotes(f.path(), "BBB").unwrap().len(), 1);
+        assert!(provider.read_quotes(f.path(), "CCC").unwrap().is_empty());
+    }
+
+    #[test]
+    fn european_layout_with_renamed_columns() {
+        let f = file(
+            "Datum;Erster;Hoch;Tief;Schluss\n\
+             04.01.2021;1.000,5;1.010,25;990;1.005,75\n",
+        );
+        let config = CsvConfig {
+            columns: "date=Datum,open=Erster,high=Hoch,low=Tief,close=Schluss"
+                .parse()
+                .unwrap(),
+            date_format: "%d.%m.%Y".into(),
+            decimal: DecimalFormat {
+                decimal_separator: ',',
+                thousands_separator: Some('.'),
+            },
+            delimiter: b';',
+            timezone: chrono_tz::Europe::Berlin,
+            currency: Some("EUR".into()),
+        };
+        let provider = CsvProvider::with_config(f.path(), config);
+        let quotes = provider.read_quotes(f.path(), "SAP.DE").unwrap();
+        assert_eq!(quotes.len(), 1);
+        let q = &quotes[0];
+        assert_eq!(q.timestamp, Utc.ymd(2021, 1, 3).and_hms(23, 0, 0));
+        assert_eq!(q.open, dec("1000.5"));
+        assert_eq!(q.high, dec("1010.25"));
+        assert_eq!(q.close, dec("1005.75"));
+        // no volume column
+        assert_eq!(q.volume, 0);
+    }
+
+    #[test]
+    fn bad_rows_say_where() {
+        let f = file("Date,Open,High,Low,Close\n2021-01-04,1,1,1,1\n2021-01-05,1,x,1,1\n");
+        let provider = CsvProvider::new(f.path());
+        match provider.read_quotes(f.path(), "X") {
+            Err(FetchError::Malformed(e)) => assert!(e.contains("line 3"), "{}", e),
+            other => panic!("{:?}", other),
+        }
+        let f = file("Date,Open,High,Low\n2021-01-04,1,1,1\n");
+        assert!(matches!(
+            provider.read_quotes(f.path(), "X"),
+            Err(FetchError::Malformed(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn dir_of_ticker_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("AAA.csv"),
+            "Date,Open,High,Low,Close,Adj Close,Volume\n\
+             2021-01-04,1,1,1,1,0.9,10\n\
+             2021-01-05,2,2,2,2,1.8,10\n\
+             2021-01-06,3,3,3,3,2.7,10\n",
+        )
+        .unwrap();
+        let provider = CsvProvider::new(dir.path());
+        let day = |d| Utc.ymd(2021, 1, d).and_hms(0, 0, 0);
+        let history = provider
+            .history("aaa", day(5), day(6), Interval::OneDay)
+            .await
+            .unwrap();
+        let adjcloses: Vec<Decimal> = history.quotes.iter().map(|q| q.adjclose).collect();
+        assert_eq!(adjcloses, vec![dec("1.8"), dec("2.7")]);
+        assert_eq!(history.timezone, Some(Tz::UTC));
+        assert!(matches!(
+            provider
+                .history("BBB", day(5), day(6), Interval::OneDay)
+                .await,
+            Err(FetchError::NotFound(_))
+        ));
+        assert_eq!(provider.info("AAA").await.unwrap().symbol, "AAA");
+        assert!(provider.info("BBB").await.is_err());
+        assert_eq!(provider.search("a").await.unwrap()[0].symbol, "AAA");
+    }
+}
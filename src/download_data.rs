@@ -1,15 +1,25 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
 
-use crate::process_data::{min_and_max, Data};
-use chrono::{DateTime, Utc};
+use crate::corporate_actions::{CorporateActions, Dividend, Split};
+use crate::interval::Interval;
+use crate::metadata::{SearchResult, TickerInfo};
+use crate::process_data::Data;
+use crate::rate_limit::Budget;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
 use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
-use std::sync::Arc;
-use yahoo_finance_api::{Quote, YahooConnector};
+use yahoo_finance_api::{Quote, YahooConnector, YahooError};
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct YQuote {
-    pub timestamp: u64,
+    /// bar start
+    pub timestamp: DateTime<Utc>,
     pub open: Decimal,
     pub high: Decimal,
     pub low: Decimal,
@@ -18,51 +28,536 @@ pub struct YQuote {
     pub adjclose: Decimal,
 }
 
-impl From<Quote> for YQuote {
-    fn from(q: Quote) -> Self {
+/// Decimal places kept per field when converting the vendor's f64s - anything past that is float noise.
+#[derive(Clone, Copy, Debug)]
+pub struct QuotePrecision {
+    pub open: u32,
+    pub high: u32,
+    pub low: u32,
+    pub close: u32,
+    pub adjclose: u32,
+}
+
+impl Default for QuotePrecision {
+    fn default() -> Self {
         Self {
-            timestamp: q.timestamp,
-            open: Decimal::from_str(&q.open.to_string()).unwrap(), //todo could be done better - chop off X digits and go via int + currently using unwrap instead of proper deserialization
-            high: Decimal::from_str(&q.high.to_string()).unwrap(),
-            low: Decimal::from_str(&q.low.to_string()).unwrap(),
+            open: 6,
+            high: 6,
+            low: 6,
+            close: 6,
+            adjclose: 6,
+        }
+    }
+}
+
+/// A vendor quote we couldn't turn into a YQuote, eg yahoo sends NaN for halted days.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuoteConversionError {
+    pub timestamp: DateTime<Utc>,
+    pub field: &'static str,
+    pub value: f64,
+}
+
+impl Error for QuoteConversionError {}
+
+impl fmt::Display for QuoteConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bad {} value {} in quote at {}",
+            self.field,
+            self.value,
+            self.timestamp.to_rfc3339()
+        )
+    }
+}
+
+impl YQuote {
+    pub fn from_quote(q: &Quote, precision: &QuotePrecision) -> Result<Self, QuoteConversionError> {
+        let timestamp = Utc.timestamp(q.timestamp as i64, 0);
+        let decimal = |field: &'static str, value: f64, dp: u32| {
+            // from_f64 gives None for NaN / inf
+            Decimal::from_f64(value)
+                .map(|d| d.round_dp(dp).normalize())
+                .ok_or(QuoteConversionError {
+                    timestamp,
+                    field,
+                    value,
+                })
+        };
+        Ok(Self {
+            timestamp,
+            open: decimal("open", q.open, precision.open)?,
+            high: decimal("high", q.high, precision.high)?,
+            low: decimal("low", q.low, precision.low)?,
             volume: q.volume,
-            close: Decimal::from_str(&q.close.to_string()).unwrap(),
-            adjclose: Decimal::from_str(&q.adjclose.to_string()).unwrap(),
+            close: decimal("close", q.close, precision.close)?,
+            adjclose: decimal("adjclose", q.adjclose, precision.adjclose)?,
+        })
+    }
+
+    /// The exchange-local date the bar traded on - what identifies a daily bar, whatever time the vendor stamps it with.
+    pub fn trading_date(&self, timezone: Tz) -> NaiveDate {
+        self.timestamp.with_timezone(&timezone).date().naive_local()
+    }
+}
+
+impl TryFrom<Quote> for YQuote {
+    type Error = QuoteConversionError;
+
+    fn try_from(q: Quote) -> Result<Self, Self::Error> {
+        Self::from_quote(&q, &QuotePrecision::default())
+    }
+}
+
+/// Converts a batch, handing back the rows that failed instead of bailing on the first one.
+pub fn convert_quotes(
+    quotes: &[Quote],
+    precision: &QuotePrecision,
+) -> (Data, Vec<QuoteConversionError>) {
+    let mut converted = Vec::with_capacity(quotes.len());
+    let mut bad = vec![];
+    for q in quotes {
+        match YQuote::from_quote(q, precision) {
+            Ok(q) => converted.push(q),
+            Err(e) => bad.push(e),
+        }
+    }
+    (converted, bad)
+}
+
+// ----------------------------------------------------------------------------- errors
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FetchError {
+    /// the vendor doesn't know the ticker
+    NotFound(String),
+    /// we hit the vendor's quota
+    RateLimited,
+    /// connection problems, timeouts, 5xx - worth trying again
+    Network(String),
+    /// got a response but couldn't make sense of it
+    Malformed(String),
+    /// the request was fine but there are no bars in it
+    EmptyRange,
+    /// we asked for something the vendor can't serve, eg 1m bars from a year ago
+    InvalidRequest(String),
+}
+
+impl FetchError {
+    /// whether trying again later could help
+    pub fn is_transient(&self) -> bool {
+        matches!(self, FetchError::RateLimited | FetchError::Network(_))
+    }
+}
+
+impl Error for FetchError {}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::NotFound(what) => write!(f, "not found: {}", what),
+            FetchError::RateLimited => write!(f, "rate limited by the provider"),
+            FetchError::Network(e) => write!(f, "network error: {}", e),
+            FetchError::Malformed(e) => write!(f, "malformed response: {}", e),
+            FetchError::EmptyRange => write!(f, "no quotes in the requested range"),
+            FetchError::InvalidRequest(e) => write!(f, "invalid request: {}", e),
+        }
+    }
+}
+
+impl From<YahooError> for FetchError {
+    fn from(e: YahooError) -> Self {
+        match e {
+            // status comes through as "Status Code: 404 Not Found"
+            YahooError::FetchFailed(status) => {
+                let code = status
+                    .split_whitespace()
+                    .find_map(|w| w.parse::<u16>().ok())
+                    .unwrap_or(0);
+                match code {
+                    404 => FetchError::NotFound(status),
+                    429 => FetchError::RateLimited,
+                    400..=499 => FetchError::InvalidRequest(status),
+                    _ => FetchError::Network(status),
+                }
+            }
+            YahooError::ConnectionFailed => FetchError::Network(e.to_string()),
+            YahooError::EmptyDataSet => FetchError::EmptyRange,
+            YahooError::DeserializeFailed(_)
+            | YahooError::InvalidJson
+            | YahooError::DataInconsistency => FetchError::Malformed(e.to_string()),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------- providers
+
+/// Price history plus whatever dividends / splits happened over the same range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    pub quotes: Data,
+    pub actions: CorporateActions,
+    /// exchange timezone, when the provider knows it
+    pub timezone: Option<Tz>,
+}
+
+impl History {
+    /// only the bars and events within from..=to
+    pub fn between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> History {
+        History {
+            quotes: self
+                .quotes
+                .iter()
+                .filter(|q| q.timestamp >= from && q.timestamp <= to)
+                .cloned()
+                .collect(),
+            actions: self.actions.between(from, to),
+            timezone: self.timezone,
+        }
+    }
+}
+
+/// Anything that can hand us a price history for a ticker.
+/// The downloader only talks to this trait, so it can be pointed at another vendor or at fixtures.
+#[async_trait]
+pub trait QuoteProvider: Send + Sync {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, FetchError>;
+
+    /// Name, exchange, currency etc. Sources that only carry prices know nothing beyond the symbol,
+    /// so by default this doesn't even check the ticker exists.
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        Ok(TickerInfo::unknown(ticker))
+    }
+
+    /// Symbols matching a company name or partial symbol, best match first.
+    async fn search(&self, _query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        Err(FetchError::InvalidRequest(
+            "this source can't search for symbols".into(),
+        ))
+    }
+
+    /// requests left before the provider's quota kicks in, if it has one
+    fn remaining_budget(&self) -> Option<Budget> {
+        None
+    }
+
+    /// Whether open/high/low/close and volume come back already restated for splits rather than as
+    /// traded, like yahoo's do. Own split adjustment has to undo that first.
+    fn split_adjusted(&self) -> bool {
+        false
+    }
+}
+
+pub struct YahooProvider {
+    connector: YahooConnector,
+    precision: QuotePrecision,
+    /// drop (and report) quotes that won't convert instead of failing the whole download
+    skip_bad_rows: bool,
+}
+
+impl Default for YahooProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl YahooProvider {
+    pub fn new() -> Self {
+        Self {
+            connector: YahooConnector::new(),
+            precision: QuotePrecision::default(),
+            skip_bad_rows: true,
         }
     }
+
+    pub fn with_precision(mut self, precision: QuotePrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn skip_bad_rows(mut self, skip: bool) -> Self {
+        self.skip_bad_rows = skip;
+        self
+    }
 }
 
+#[async_trait]
+impl QuoteProvider for YahooProvider {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, FetchError> {
+        let response = self
+            .connector
+            .get_quote_history_interval(ticker, from, to, interval.as_str())
+            .await?;
+        let (quotes, bad) = convert_quotes(&response.quotes()?, &self.precision);
+        if let Some(e) = bad.first() {
+            if !self.skip_bad_rows {
+                return Err(FetchError::Malformed(format!("{}: {}", ticker, e)));
+            }
+        }
+        for e in &bad {
+            println!("{}: skipping {}", ticker, e);
+        }
+        let actions = CorporateActions {
+            dividends: response.dividends()?.iter().map(Dividend::from).collect(),
+            splits: response.splits()?.iter().map(Split::from).collect(),
+        };
+        // eg "America/New_York" - gmtoffset alone would get DST wrong
+        let timezone = response
+            .chart
+            .result
+            .first()
+            .and_then(|r| r.meta.exchange_timezone_name.parse().ok());
+        Ok(History {
+            quotes,
+            actions,
+            timezone,
+        })
+    }
+
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        let response = self.connector.get_latest_quotes(ticker, "1d").await?;
+        let meta = &response
+            .chart
+            .result
+            .first()
+            .ok_or_else(|| FetchError::NotFound(ticker.to_string()))?
+            .meta;
+        let mut info = TickerInfo::from(meta);
+        // only the search endpoint has the company name - nice to have, not worth failing over
+        if let Ok(found) = self.search(ticker).await {
+            info.long_name = found
+                .into_iter()
+                .find(|r| r.symbol.eq_ignore_ascii_case(&info.symbol))
+                .map(|r| r.name)
+                .filter(|name| !name.is_empty());
+        }
+        Ok(info)
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        let found = self.connector.search_ticker_opt(query).await?;
+        Ok(found.quotes.iter().map(SearchResult::from).collect())
+    }
+
+    fn split_adjusted(&self) -> bool {
+        true
+    }
+}
+
+/// Asks `primary` first and only goes to `fallback` when that fails or comes back empty,
+/// eg yahoo first and a dir of csv files for whatever yahoo doesn't have. The primary's error wins if both fail.
+pub struct FallbackProvider {
+    primary: Arc<dyn QuoteProvider>,
+    fallback: Arc<dyn QuoteProvider>,
+}
+
+impl FallbackProvider {
+    pub fn new(primary: Arc<dyn QuoteProvider>, fallback: Arc<dyn QuoteProvider>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait]
+impl QuoteProvider for FallbackProvider {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, FetchError> {
+        let err = match self.primary.history(ticker, from, to, interval).await {
+            Ok(history) if !history.quotes.is_empty() => return Ok(history),
+            Ok(_) => FetchError::EmptyRange,
+            Err(e) => e,
+        };
+        self.fallback
+            .history(ticker, from, to, interval)
+            .await
+            .map_err(|_| err)
+    }
+
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        match self.primary.info(ticker).await {
+            Ok(info) => Ok(info),
+            Err(e) => self.fallback.info(ticker).await.map_err(|_| e),
+        }
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        match self.primary.search(query).await {
+            Ok(found) => Ok(found),
+            Err(e) => self.fallback.search(query).await.map_err(|_| e),
+        }
+    }
+
+    fn remaining_budget(&self) -> Option<Budget> {
+        self.primary.remaining_budget()
+    }
+
+    fn split_adjusted(&self) -> bool {
+        self.primary.split_adjusted()
+    }
+}
+
+/// In-memory provider - serves whatever quotes it was loaded with, no network involved.
+/// Handy for tests and for running the whole pipeline offline.
+/// Like yahoo, a range with no bars in it is an EmptyRange error.
+#[derive(Default, Clone, Debug)]
+pub struct FixtureProvider {
+    histories: HashMap<String, History>,
+    infos: HashMap<String, TickerInfo>,
+}
+
+impl FixtureProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_quotes(mut self, ticker: &str, quotes: Data) -> Self {
+        self.insert(ticker, quotes);
+        self
+    }
+
+    pub fn with_actions(mut self, ticker: &str, actions: CorporateActions) -> Self {
+        self.histories
+            .entry(ticker.to_uppercase())
+            .or_default()
+            .actions = actions;
+        self
+    }
+
+    pub fn with_timezone(mut self, ticker: &str, timezone: Tz) -> Self {
+        self.histories
+            .entry(ticker.to_uppercase())
+            .or_default()
+            .timezone = Some(timezone);
+        self
+    }
+
+    pub fn with_info(mut self, info: TickerInfo) -> Self {
+        self.infos.insert(info.symbol.to_uppercase(), info);
+        self
+    }
+
+    pub fn insert(&mut self, ticker: &str, quotes: Data) {
+        self.histories
+            .entry(ticker.to_uppercase())
+            .or_default()
+            .quotes = quotes;
+    }
+}
+
+#[async_trait]
+impl QuoteProvider for FixtureProvider {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        _interval: Interval,
+    ) -> Result<History, FetchError> {
+        let history = self
+            .histories
+            .get(&ticker.to_uppercase())
+            .ok_or_else(|| FetchError::NotFound(ticker.to_string()))?;
+        let history = history.between(from, to);
+        if history.quotes.is_empty() {
+            return Err(FetchError::EmptyRange);
+        }
+        Ok(history)
+    }
+
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        let key = ticker.to_uppercase();
+        if let Some(info) = self.infos.get(&key) {
+            return Ok(info.clone());
+        }
+        let history = self
+            .histories
+            .get(&key)
+            .ok_or_else(|| FetchError::NotFound(ticker.to_string()))?;
+        Ok(TickerInfo {
+            timezone: history.timezone,
+            ..TickerInfo::unknown(ticker)
+        })
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        let query = query.to_uppercase();
+        let mut found: Vec<SearchResult> = self
+            .histories
+            .keys()
+            .chain(self.infos.keys())
+            .filter(|symbol| symbol.contains(&query))
+            .map(|symbol| {
+                let info = self.infos.get(symbol);
+                SearchResult {
+                    symbol: symbol.clone(),
+                    name: info.and_then(|i| i.long_name.clone()).unwrap_or_default(),
+                    exchange: info.and_then(|i| i.exchange.clone()).unwrap_or_default(),
+                    instrument_type: info
+                        .and_then(|i| i.instrument_type.clone())
+                        .unwrap_or_default(),
+                }
+            })
+            .collect();
+        found.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        found.dedup();
+        Ok(found)
+    }
+}
+
+// ----------------------------------------------------------------------------- fetch
+
 ///API Limits
 // - Using the Public API (without authentication), you are limited to 2,000 requests per hour per IP (or up to a total of 48,000 requests a day).
-// - Seems that 1h is resolution limit
+//   Wrap the provider in a RateLimitedProvider to stay under that.
+// - Intraday bars only go back so far and long intraday ranges have to be split up - see Interval::max_lookback / max_request_span.
+//   The range is expected to have gone through Interval::validate already.
 pub async fn fetch_stonks_data(
+    provider: &dyn QuoteProvider,
     ticker: String,
     from: DateTime<Utc>,
     to: DateTime<Utc>,
-) -> Result<Data, Box<dyn Error>> {
+    interval: Interval,
+) -> Result<History, FetchError> {
     println!("START downloading...");
 
-    let provider = YahooConnector::new();
-
-    std::thread::sleep(std::time::Duration::from_secs(1));
-
-    //todo frequency - play with 1d/1h should work for both
-    let response = match provider
-        .get_quote_history_interval(&ticker, from, to, "1d")
-        .await
-    {
-        Ok(r) => r,
-        Err(e) => {
-            println!("An ERROR occured: {:?}", e);
-            return Err(Box::new(e));
-        }
-    };
-    let mut quotes: Vec<YQuote> = response
-        .quotes()
-        .unwrap()
-        .into_iter()
-        .map(|q| q.into())
-        .collect();
+    let mut quotes = vec![];
+    let mut actions = CorporateActions::default();
+    let mut timezone = None;
+    for (chunk_from, chunk_to) in interval.chunks(from, to) {
+        match provider
+            .history(&ticker, chunk_from, chunk_to, interval)
+            .await
+        {
+            Ok(h) => {
+                quotes.extend(h.quotes);
+                actions.merge(h.actions);
+                timezone = timezone.or(h.timezone);
+            }
+            // a chunk that's all weekend or holiday - the rest can still have bars
+            Err(FetchError::EmptyRange) => continue,
+            Err(e) => {
+                println!("An ERROR occured: {:?}", e);
+                return Err(e);
+            }
+        };
+    }
 
     // for quote in &quotes {
     //     println!("{:#?}", quote);
@@ -71,8 +566,175 @@ pub async fn fetch_stonks_data(
     // }
 
     quotes.sort_by_cached_key(|k| k.timestamp); //just in case aren't sorted already
+    quotes.dedup_by_key(|k| k.timestamp); //chunk boundaries overlap by one bar
+
+    if quotes.is_empty() {
+        return Err(FetchError::EmptyRange);
+    }
+
+    if let Some(budget) = provider.remaining_budget() {
+        println!("API budget left: {}", budget);
+    }
 
     println!("END downloading...");
 
-    Ok(quotes)
+    Ok(History {
+        quotes,
+        actions,
+        timezone,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_data::{process_data, ProcessConfig};
+
+    fn day(d: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 1, d).and_hms(14, 30, 0)
+    }
+
+    fn bar(d: u32, close: i64) -> YQuote {
+        let close = Decimal::from(close);
+        YQuote {
+            timestamp: day(d),
+            open: close,
+            high: close,
+            low: close,
+            volume: 100,
+            close,
+            adjclose: close,
+        }
+    }
+
+    /// mon 4th to fri 8th
+    fn fixture() -> FixtureProvider {
+        FixtureProvider::new()
+            .with_quotes(
+                "TEST",
+                vec![bar(4, 10), bar(5, 12), bar(6, 9), bar(7, 11), bar(8, 15)],
+            )
+            .with_timezone("TEST", chrono_tz::America::New_York)
+    }
+
+    #[async_std::test]
+    async fn fixture_through_the_whole_pipeline() {
+        let provider = fixture();
+        let info = provider.info("test").await.unwrap();
+        let history = fetch_stonks_data(&provider, "TEST".into(), day(4), day(8), Interval::OneDay)
+            .await
+            .unwrap();
+        assert_eq!(history.quotes.len(), 5);
+        assert_eq!(history.timezone, Some(chrono_tz::America::New_York));
+
+        let processed = process_data(history, &info, &ProcessConfig::default()).unwrap();
+        assert_eq!(processed.min_, Decimal::from(9));
+        assert_eq!(processed.max_, Decimal::from(15));
+        assert_eq!(processed.abs_diff, Decimal::from(5));
+        assert_eq!(processed.percent_diff, Decimal::new(5, 1));
+        // fewer bars than a 30 day window
+        assert!(processed.smas.is_empty());
+        assert_eq!(processed.data.len(), 5);
+        assert_eq!(processed.timezone, chrono_tz::America::New_York);
+    }
+
+    #[async_std::test]
+    async fn only_the_requested_range() {
+        let history =
+            fetch_stonks_data(&fixture(), "TEST".into(), day(5), day(7), Interval::OneDay)
+                .await
+                .unwrap();
+        let closes: Vec<Decimal> = history.quotes.iter().map(|q| q.close).collect();
+        assert_eq!(closes, vec![12.into(), 9.into(), 11.into()]);
+    }
+
+    #[async_std::test]
+    async fn nothing_in_range_is_empty() {
+        let err = fetch_stonks_data(
+            &fixture(),
+            "TEST".into(),
+            day(11),
+            day(15),
+            Interval::OneDay,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err, FetchError::EmptyRange);
+    }
+
+    #[async_std::test]
+    async fn empty_chunks_are_skipped() {
+        // 1m bars come in 7 day chunks - the middle one here has nothing in it
+        let minute = |d, h| YQuote {
+            timestamp: Utc.ymd(2021, 1, d).and_hms(h, 0, 0),
+            ..bar(d, 1)
+        };
+        let provider = FixtureProvider::new()
+            .with_quotes("TEST", vec![minute(4, 15), minute(5, 15), minute(19, 15)]);
+        let from = Utc.ymd(2021, 1, 4).and_hms(0, 0, 0);
+        let to = Utc.ymd(2021, 1, 20).and_hms(0, 0, 0);
+        assert_eq!(Interval::OneMinute.chunks(from, to).len(), 3);
+        let history = fetch_stonks_data(&provider, "TEST".into(), from, to, Interval::OneMinute)
+            .await
+            .unwrap();
+        assert_eq!(history.quotes.len(), 3);
+    }
+
+    #[test]
+    fn yahoo_errors_are_classified() {
+        let status = |s: &str| FetchError::from(YahooError::FetchFailed(s.into()));
+        assert_eq!(
+            status("Status Code: 404 Not Found"),
+            FetchError::NotFound("Status Code: 404 Not Found".into())
+        );
+        assert_eq!(
+            status("Status Code: 429 Too Many Requests"),
+            FetchError::RateLimited
+        );
+        assert_eq!(
+            status("Status Code: 400 Bad Request"),
+            FetchError::InvalidRequest("Status Code: 400 Bad Request".into())
+        );
+        assert_eq!(
+            status("Status Code: 503 Service Unavailable"),
+            FetchError::Network("Status Code: 503 Service Unavailable".into())
+        );
+        assert!(matches!(
+            FetchError::from(YahooError::ConnectionFailed),
+            FetchError::Network(_)
+        ));
+        assert_eq!(
+            FetchError::from(YahooError::EmptyDataSet),
+            FetchError::EmptyRange
+        );
+        assert!(matches!(
+            FetchError::from(YahooError::InvalidJson),
+            FetchError::Malformed(_)
+        ));
+
+        assert!(FetchError::RateLimited.is_transient());
+        assert!(FetchError::Network(String::new()).is_transient());
+        assert!(!FetchError::NotFound(String::new()).is_transient());
+        assert!(!FetchError::InvalidRequest(String::new()).is_transient());
+        assert!(!FetchError::EmptyRange.is_transient());
+    }
+
+    #[async_std::test]
+    async fn unknown_ticker() {
+        let provider = fixture();
+        let err = fetch_stonks_data(&provider, "NOPE".into(), day(4), day(8), Interval::OneDay)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FetchError::NotFound(_)));
+        assert!(matches!(
+            provider.info("NOPE").await,
+            Err(FetchError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn nothing_to_process() {
+        let info = TickerInfo::unknown("TEST");
+        assert!(process_data(History::default(), &info, &ProcessConfig::default()).is_none());
+    }
 }
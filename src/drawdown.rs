@@ -0,0 +1,280 @@
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::interval::Interval;
+use crate::risk::annualized_return;
+use crate::streaming::{run, RollingMinMax};
+
+/// One peak to recovery episode.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Drawdown {
+    pub peak: DateTime<Utc>,
+    pub trough: DateTime<Utc>,
+    /// first bar back at the peak, None = still under water at the end
+    pub recovery: Option<DateTime<Utc>>,
+    /// trough against peak, as a negative fraction
+    pub depth: Decimal,
+    /// bars from the peak to the recovery, or to the last bar
+    pub bars: usize,
+    /// time from the peak to the recovery, or to the last bar
+    pub duration: Duration,
+}
+
+impl Drawdown {
+    pub fn is_recovered(&self) -> bool {
+        self.recovery.is_some()
+    }
+}
+
+/// How far under its running high the series is at every bar - 0 at a new high, -0.25 a quarter below.
+pub fn underwater(series: &[Decimal]) -> Vec<Decimal> {
+    run(RollingMinMax::expanding(), series)
+        .into_iter()
+        .zip(series)
+        .map(|((_, peak), price)| {
+            if peak.is_zero() {
+                Decimal::zero()
+            } else {
+                price / peak - Decimal::one()
+            }
+        })
+        .collect()
+}
+
+/// Every drawdown episode in time order. `timestamps` and `series` go bar for bar.
+pub fn drawdowns(timestamps: &[DateTime<Utc>], series: &[Decimal]) -> Vec<Drawdown> {
+    let underwater = underwater(series);
+    let mut episodes = vec![];
+    // bar the current episode peaked at, and its deepest bar so far
+    let mut open: Option<(usize, usize)> = None;
+    for (i, depth) in underwater.iter().enumerate() {
+        match open {
+            None if depth.is_sign_negative() && !depth.is_zero() => open = Some((i - 1, i)),
+            Some((peak, trough)) if depth.is_zero() => {
+                episodes.push(episode(timestamps, &underwater, peak, trough, Some(i)));
+                open = None;
+            }
+            Some((peak, trough)) if *depth < underwater[trough] => open = Some((peak, i)),
+            _ => {}
+        }
+    }
+    if let Some((peak, trough)) = open {
+        episodes.push(episode(timestamps, &underwater, peak, trough, None));
+    }
+    episodes
+}
+
+fn episode(
+    timestamps: &[DateTime<Utc>],
+    underwater: &[Decimal],
+    peak: usize,
+    trough: usize,
+    recovery: Option<usize>,
+) -> Drawdown {
+    let end = recovery.unwrap_or(timestamps.len() - 1);
+    Drawdown {
+        peak: timestamps[peak],
+        trough: timestamps[trough],
+        recovery: recovery.map(|i| timestamps[i]),
+        depth: underwater[trough],
+        bars: end - peak,
+        duration: timestamps[end] - timestamps[peak],
+    }
+}
+
+/// Drawdown summary for one series.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DrawdownStats {
+    pub underwater: Vec<Decimal>,
+    /// the deepest episode
+    pub max_drawdown: Option<Drawdown>,
+    /// the episode that took longest to get back (or is still going)
+    pub longest: Option<Drawdown>,
+    /// annualised return over the max drawdown's depth
+    pub calmar: Option<Decimal>,
+    /// the deepest episodes, deepest first
+    pub episodes: Vec<Drawdown>,
+}
+
+impl DrawdownStats {
+    /// names of the summary columns `row` fills in
+    pub fn columns() -> Vec<String> {
+        [
+            "max dd %",
+            "dd peak",
+            "dd trough",
+            "dd recovery",
+            "longest dd days",
+            "calmar",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+    }
+
+    /// keeps the `top` deepest episodes
+    pub fn compute(
+        timestamps: &[DateTime<Utc>],
+        series: &[Decimal],
+        interval: Interval,
+        top: usize,
+    ) -> Self {
+        let mut episodes = drawdowns(timestamps, series);
+        let longest = episodes.iter().max_by_key(|d| d.duration).cloned();
+        episodes.sort_by_key(|d| d.depth);
+        let max_drawdown = episodes.first().cloned();
+        let calmar = match (&max_drawdown, annualized_return(series, interval)) {
+            (Some(dd), Some(ret)) => Some(ret / dd.depth.abs()),
+            _ => None,
+        };
+        episodes.truncate(top);
+        Self {
+            underwater: underwater(series),
+            max_drawdown,
+            longest,
+            calmar,
+            episodes,
+        }
+    }
+
+    /// the summary columns, with `fmt` for the dates - blank where there was no drawdown
+    pub fn row(&self, fmt: impl Fn(DateTime<Utc>) -> String) -> Vec<String> {
+        let max = self.max_drawdown.as_ref();
+        vec![
+            max.map_or(String::new(), |d| {
+                (d.depth * Decimal::from(100)).round_dp(2).to_string()
+            }),
+            max.map_or(String::new(), |d| fmt(d.peak)),
+            max.map_or(String::new(), |d| fmt(d.trough)),
+            max.and_then(|d| d.recovery).map_or(String::new(), &fmt),
+            self.longest
+                .as_ref()
+                .map_or(String::new(), |d| d.duration.num_days().to_string()),
+            self.calmar
+                .map_or(String::new(), |c| c.round_dp(2).to_string()),
+        ]
+    }
+
+    /// the episode table, one line per episode
+    pub fn table(&self, fmt: impl Fn(DateTime<Utc>) -> String) -> Vec<String> {
+        self.episodes
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                format!(
+                    "{:>3}. {:>8}%  peak {}  trough {}  {}  ({} bars, {} days)",
+                    i + 1,
+                    (d.depth * Decimal::from(100)).round_dp(2),
+                    fmt(d.peak),
+                    fmt(d.trough),
+                    d.recovery.map_or("not recovered".to_string(), |r| format!(
+                        "recovered {}",
+                        fmt(r)
+                    )),
+                    d.bars,
+                    d.duration.num_days()
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn day(i: usize) -> DateTime<Utc> {
+        Utc.ymd(2021, 1, 4).and_hms(21, 0, 0) + Duration::days(i as i64)
+    }
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    // 110 -> 88 (-20%) and back on bar 5, then 120 -> 90 (-25%) still under water at the end
+    fn series() -> (Vec<DateTime<Utc>>, Vec<Decimal>) {
+        let prices: Vec<Decimal> = [100, 110, 99, 88, 99, 110, 120, 108, 90, 102]
+            .iter()
+            .map(|&p| Decimal::from(p))
+            .collect();
+        ((0..prices.len()).map(day).collect(), prices)
+    }
+
+    #[test]
+    fn underwater_against_the_running_high() {
+        let (_, prices) = series();
+        assert_eq!(
+            underwater(&prices),
+            ["0", "0", "-0.1", "-0.2", "-0.1", "0", "0", "-0.1", "-0.25", "-0.15"]
+                .iter()
+                .map(|d| dec(d))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn one_recovered_episode_and_one_open() {
+        let (timestamps, prices) = series();
+        let episodes = drawdowns(&timestamps, &prices);
+        assert_eq!(
+            episodes,
+            vec![
+                Drawdown {
+                    peak: day(1),
+                    trough: day(3),
+                    recovery: Some(day(5)),
+                    depth: dec("-0.2"),
+                    bars: 4,
+                    duration: Duration::days(4),
+                },
+                Drawdown {
+                    peak: day(6),
+                    trough: day(8),
+                    recovery: None,
+                    depth: dec("-0.25"),
+                    bars: 3,
+                    duration: Duration::days(3),
+                },
+            ]
+        );
+        assert!(episodes[0].is_recovered());
+        assert!(!episodes[1].is_recovered());
+    }
+
+    #[test]
+    fn stats_rank_the_episodes() {
+        let (timestamps, prices) = series();
+        let stats = DrawdownStats::compute(&timestamps, &prices, Interval::OneDay, 5);
+        // deepest first
+        let peaks: Vec<DateTime<Utc>> = stats.episodes.iter().map(|d| d.peak).collect();
+        assert_eq!(peaks, vec![day(6), day(1)]);
+        assert_eq!(
+            stats.max_drawdown.as_ref().map(|d| d.depth),
+            Some(dec("-0.25"))
+        );
+        // the shallower one took longer to get back
+        assert_eq!(stats.longest.as_ref().map(|d| d.peak), Some(day(1)));
+        // 2% over 9 daily bars, compounded over 252, against the 25%
+        let calmar = (1.02f64.powf(252.0 / 9.0) - 1.0) / 0.25;
+        assert!((stats.calmar.unwrap().to_f64().unwrap() - calmar).abs() < 1e-9);
+
+        let top = DrawdownStats::compute(&timestamps, &prices, Interval::OneDay, 1);
+        assert_eq!(top.episodes.len(), 1);
+        assert_eq!(top.episodes[0].peak, day(6));
+        // the longest still counts the ones cut from the table
+        assert_eq!(top.longest.map(|d| d.peak), Some(day(1)));
+    }
+
+    #[test]
+    fn no_drawdown_on_a_rising_series() {
+        let prices: Vec<Decimal> = (1..=5).map(Decimal::from).collect();
+        let timestamps: Vec<DateTime<Utc>> = (0..5).map(day).collect();
+        let stats = DrawdownStats::compute(&timestamps, &prices, Interval::OneDay, 5);
+        assert!(stats.episodes.is_empty());
+        assert_eq!(stats.max_drawdown, None);
+        assert!(stats.row(|t| t.to_rfc3339()).iter().all(|c| c.is_empty()));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::download_data::{FetchError, History, QuoteProvider, YQuote};
+use crate::interval::Interval;
+
+/// Splits a vendor currency code into the ISO currency and what one quoted unit is worth in it.
+/// LSE prices mostly come in pence (GBp / GBX), Johannesburg in cents, Tel Aviv in agorot.
+pub fn normalize_currency(code: &str) -> (String, Decimal) {
+    let cents = Decimal::new(1, 2);
+    match code {
+        "GBp" | "GBX" => ("GBP".into(), cents),
+        "ZAc" | "ZAC" => ("ZAR".into(), cents),
+        "ILA" => ("ILS".into(), cents),
+        _ => (code.to_uppercase(), Decimal::one()),
+    }
+}
+
+/// yahoo's symbol for a currency pair, eg EURUSD=X is the price of 1 EUR in USD
+pub fn fx_symbol(from: &str, to: &str) -> String {
+    format!("{}{}=X", from, to)
+}
+
+/// Daily rates for one pair, keyed by date.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateSeries {
+    pub from: String,
+    pub to: String,
+    pub rates: BTreeMap<NaiveDate, Decimal>,
+}
+
+impl RateSeries {
+    fn identity(currency: &str) -> Self {
+        Self {
+            from: currency.into(),
+            to: currency.into(),
+            rates: BTreeMap::new(),
+        }
+    }
+
+    /// The last rate fixed on or before `date` - fx doesn't fix on weekends and holidays differ between markets.
+    /// Dates before the first fix get the first one.
+    pub fn rate_on(&self, date: NaiveDate) -> Option<Decimal> {
+        if self.from == self.to {
+            return Some(Decimal::one());
+        }
+        self.rates
+            .range(..=date)
+            .next_back()
+            .or_else(|| self.rates.iter().next())
+            .map(|(_, rate)| *rate)
+    }
+
+    fn from_history(from: &str, to: &str, history: &History) -> Self {
+        let timezone = history.timezone.unwrap_or(Tz::UTC);
+        Self {
+            from: from.into(),
+            to: to.into(),
+            rates: history
+                .quotes
+                .iter()
+                .filter(|q| !q.close.is_zero())
+                .map(|q| (q.trading_date(timezone), q.close))
+                .collect(),
+        }
+    }
+
+    fn inverted(self) -> Self {
+        Self {
+            from: self.to,
+            to: self.from,
+            rates: self
+                .rates
+                .into_iter()
+                .map(|(date, rate)| (date, Decimal::one() / rate))
+                .collect(),
+        }
+    }
+}
+
+/// Turns prices quoted in one currency into the reporting currency, using rates from any provider
+/// that knows fx pairs as tickers (yahoo does, or a dir of `EURUSD=X.csv` style files).
+pub struct FxConverter {
+    provider: Arc<dyn QuoteProvider>,
+    report_currency: String,
+}
+
+impl FxConverter {
+    pub fn new(provider: Arc<dyn QuoteProvider>, report_currency: &str) -> Self {
+        Self {
+            provider,
+            report_currency: normalize_currency(report_currency).0,
+        }
+    }
+
+    pub fn report_currency(&self) -> &str {
+        &self.report_currency
+    }
+
+    /// Daily from -> to rates covering the range. Falls back to the inverse pair when the direct one isn't there.
+    pub async fn rates(
+        &self,
+        from_ccy: &str,
+        to_ccy: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<RateSeries, FetchError> {
+        if from_ccy == to_ccy {
+            return Ok(RateSeries::identity(from_ccy));
+        }
+        // start a bit early so the first bar has a fix even after a long weekend
+        let from = from - Duration::days(7);
+        let direct = self
+            .provider
+            .history(&fx_symbol(from_ccy, to_ccy), from, to, Interval::OneDay)
+            .await;
+        let series = match direct {
+            Ok(history) if !history.quotes.is_empty() => {
+                RateSeries::from_history(from_ccy, to_ccy, &history)
+            }
+            Ok(_) | Err(FetchError::NotFound(_)) | Err(FetchError::EmptyRange) => {
+                let inverse = self
+                    .provider
+                    .history(&fx_symbol(to_ccy, from_ccy), from, to, Interval::OneDay)
+                    .await?;
+                RateSeries::from_history(to_ccy, from_ccy, &inverse).inverted()
+            }
+            Err(e) => return Err(e),
+        };
+        if series.rates.is_empty() {
+            return Err(FetchError::NotFound(format!(
+                "no {}/{} rates",
+                from_ccy, to_ccy
+            )));
+        }
+        Ok(series)
+    }
+
+    /// Converts every price (and dividend) in `history` from `currency` into the reporting currency,
+    /// bar by bar at that day's rate. `timezone` dates the bars when the history doesn't say.
+    pub async fn convert(
+        &self,
+        history: History,
+        currency: &str,
+        timezone: Tz,
+    ) -> Result<History, FetchError> {
+        let (iso, unit) = normalize_currency(currency);
+        let (first, last) = match (history.quotes.first(), history.quotes.last()) {
+            (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+            _ => return Ok(history),
+        };
+        let rates = self.rates(&iso, &self.report_currency, first, last).await?;
+        let timezone = history.timezone.unwrap_or(timezone);
+        let rate_on = |date: NaiveDate| rates.rate_on(date).map(|rate| rate * unit);
+
+        let mut converted = history;
+        for q in converted.quotes.iter_mut() {
+            // rate_on only comes back empty for an empty series, which rates() already ruled out
+            let rate = rate_on(q.trading_date(timezone)).unwrap_or_else(Decimal::one);
+            *q = YQuote {
+                open: q.open * rate,
+                high: q.high * rate,
+                low: q.low * rate,
+                close: q.close * rate,
+                adjclose: q.adjclose * rate,
+                ..q.clone()
+            };
+        }
+        for d in converted.actions.dividends.iter_mut() {
+            let date = d.ex_date.with_timezone(&timezone).date().naive_local();
+            d.amount *= rate_on(date).unwrap_or_else(Decimal::one);
+        }
+        Ok(converted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::corporate_actions::{CorporateActions, Dividend};
+    use crate::download_data::FixtureProvider;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn at(d: u32, h: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 1, d).and_hms(h, 0, 0)
+    }
+
+    /// one bar at `h` o'clock utc on each day, closing at the given price
+    fn bars(h: u32, closes: &[(u32, &str)]) -> Vec<YQuote> {
+        closes
+            .iter()
+            .map(|&(d, close)| {
+                let close = dec(close);
+                YQuote {
+                    timestamp: at(d, h),
+                    open: close,
+                    high: close,
+                    low: close,
+                    volume: 100,
+                    close,
+                    adjclose: close,
+                }
+            })
+            .collect()
+    }
+
+    fn converter(pair: &str, rates: &[(u32, &str)]) -> FxConverter {
+        let provider = FixtureProvider::new().with_quotes(pair, bars(22, rates));
+        FxConverter::new(Arc::new(provider), "usd")
+    }
+
+    #[test]
+    fn minor_units() {
+        let cents = dec("0.01");
+        assert_eq!(normalize_currency("GBp"), ("GBP".into(), cents));
+        assert_eq!(normalize_currency("GBX"), ("GBP".into(), cents));
+        assert_eq!(normalize_currency("ZAc"), ("ZAR".into(), cents));
+        assert_eq!(normalize_currency("ILA"), ("ILS".into(), cents));
+        assert_eq!(normalize_currency("GBP"), ("GBP".into(), Decimal::one()));
+        assert_eq!(normalize_currency("eur"), ("EUR".into(), Decimal::one()));
+    }
+
+    #[test]
+    fn rate_on_takes_the_last_fix() {
+        let series = RateSeries {
+            from: "GBP".into(),
+            to: "USD".into(),
+            rates: vec![
+                (NaiveDate::from_ymd(2021, 1, 4), dec("1.3")),
+                (NaiveDate::from_ymd(2021, 1, 6), dec("1.4")),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        assert_eq!(
+            series.rate_on(NaiveDate::from_ymd(2021, 1, 5)),
+            Some(dec("1.3"))
+        );
+        assert_eq!(
+            series.rate_on(NaiveDate::from_ymd(2021, 1, 9)),
+            Some(dec("1.4"))
+        );
+        // before the first fix
+        assert_eq!(
+            series.rate_on(NaiveDate::from_ymd(2021, 1, 1)),
+            Some(dec("1.3"))
+        );
+        let same = RateSeries::identity("USD");
+        assert_eq!(
+            same.rate_on(NaiveDate::from_ymd(2021, 1, 1)),
+            Some(Decimal::one())
+        );
+    }
+
+    #[async_std::test]
+    async fn falls_back_to_the_inverse_pair() {
+        // only USDGBP=X is there
+        let fx = converter("USDGBP=X", &[(4, "0.8"), (5, "0.5")]);
+        let rates = fx.rates("GBP", "USD", at(4, 0), at(5, 23)).await.unwrap();
+        assert_eq!((rates.from.as_str(), rates.to.as_str()), ("GBP", "USD"));
+        assert_eq!(
+            rates.rate_on(NaiveDate::from_ymd(2021, 1, 4)),
+            Some(dec("1.25"))
+        );
+        assert_eq!(
+            rates.rate_on(NaiveDate::from_ymd(2021, 1, 5)),
+            Some(dec("2"))
+        );
+
+        let err = fx
+            .rates("EUR", "USD", at(4, 0), at(5, 23))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FetchError::NotFound(_)));
+    }
+
+    #[async_std::test]
+    async fn convert_scales_prices_and_dividends() {
+        // no fix on the 6th, so it gets the 5th's
+        let fx = converter("GBPUSD=X", &[(4, "1.3"), (5, "1.4")]);
+        let history = History {
+            quotes: bars(12, &[(4, "100"), (5, "100"), (6, "200")]),
+            actions: CorporateActions {
+                dividends: vec![Dividend {
+                    ex_date: at(5, 8),
+                    amount: dec("5"),
+                }],
+                ..Default::default()
+            },
+            timezone: None,
+        };
+        let converted = fx
+            .convert(history, "GBp", chrono_tz::Europe::London)
+            .await
+            .unwrap();
+        let closes: Vec<Decimal> = converted.quotes.iter().map(|q| q.close).collect();
+        assert_eq!(closes, vec![dec("1.3"), dec("1.4"), dec("2.8")]);
+        let q = &converted.quotes[0];
+        assert_eq!(
+            (q.open, q.high, q.low, q.adjclose),
+            (dec("1.3"), dec("1.3"), dec("1.3"), dec("1.3"))
+        );
+        assert_eq!(q.volume, 100);
+        assert_eq!(converted.actions.dividends[0].amount, dec("0.07"));
+    }
+
+    #[async_std::test]
+    async fn same_currency_is_left_alone() {
+        // the provider has no rates at all, so this would fail if it asked
+        let fx = FxConverter::new(Arc::new(FixtureProvider::new()), "USD");
+        let history = History {
+            quotes: bars(12, &[(4, "100")]),
+            ..Default::default()
+        };
+        let converted = fx.convert(history.clone(), "usd", Tz::UTC).await.unwrap();
+        assert_eq!(converted, history);
+    }
+}
@@ -0,0 +1,1346 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::download_data::YQuote;
+use crate::process_data::n_window_sma;
+use crate::streaming::{
+    run, PriceField, RollingMinMax, RollingVariance, StreamingEma, StreamingIndicator,
+};
+
+// ----------------------------------------------------------------------------- moving averages
+// Same conventions as n_window_sma: None when the series is too short for even one value, otherwise
+// only the values past the warm-up, so the output is shorter than the input and lines up with its end.
+
+/// Exponential moving average, seeded with the sma of the first n values. len - n + 1 values.
+pub fn ema(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    if n == 0 || n > series.len() {
+        return None;
+    }
+    Some(run(StreamingEma::new(n), series))
+}
+
+/// Linearly weighted moving average - the newest value weighs n, the oldest 1. len - n + 1 values.
+pub fn wma(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    if n == 0 || n > series.len() {
+        return None;
+    }
+    let total_weight = Decimal::from(n * (n + 1) / 2);
+    Some(
+        series
+            .windows(n)
+            .map(|w| {
+                w.iter()
+                    .enumerate()
+                    .map(|(i, price)| *price * Decimal::from(i + 1))
+                    .sum::<Decimal>()
+                    / total_weight
+            })
+            .collect(),
+    )
+}
+
+/// a*x + b*y element-wise over the tails of both series, so they line up at the end
+fn combine(a: Decimal, x: &[Decimal], b: Decimal, y: &[Decimal]) -> Vec<Decimal> {
+    let len = x.len().min(y.len());
+    x[x.len() - len..]
+        .iter()
+        .zip(&y[y.len() - len..])
+        .map(|(x, y)| a * *x + b * *y)
+        .collect()
+}
+
+/// Double ema, 2*ema - ema(ema). len - 2(n - 1) values.
+pub fn dema(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    let e1 = ema(n, series)?;
+    let e2 = ema(n, &e1)?;
+    Some(combine(Decimal::from(2), &e1, Decimal::from(-1), &e2))
+}
+
+/// Triple ema, 3*ema - 3*ema(ema) + ema(ema(ema)). len - 3(n - 1) values.
+pub fn tema(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    let e1 = ema(n, series)?;
+    let e2 = ema(n, &e1)?;
+    let e3 = ema(n, &e2)?;
+    let first = combine(Decimal::from(3), &e1, Decimal::from(-3), &e2);
+    Some(combine(Decimal::one(), &first, Decimal::one(), &e3))
+}
+
+/// Hull moving average, wma(2*wma(n/2) - wma(n), sqrt(n)). len - (n - 1) - (sqrt(n) - 1) values.
+pub fn hma(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    if n < 2 {
+        return None;
+    }
+    let half = wma(n / 2, series)?;
+    let full = wma(n, series)?;
+    let raw = combine(Decimal::from(2), &half, Decimal::from(-1), &full);
+    wma(((n as f64).sqrt().round() as usize).max(1), &raw)
+}
+
+// ----------------------------------------------------------------------------- rolling stats
+
+/// (min, max) over each window of n values. len - n + 1 values.
+pub fn rolling_min_max(n: usize, series: &[Decimal]) -> Option<Vec<(Decimal, Decimal)>> {
+    if n == 0 || n > series.len() {
+        return None;
+    }
+    Some(run(RollingMinMax::new(n), series))
+}
+
+/// Sample variance over each window of n values, n >= 2. len - n + 1 values.
+pub fn rolling_variance(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    if n < 2 || n > series.len() {
+        return None;
+    }
+    Some(run(RollingVariance::new(n), series))
+}
+
+/// Sample standard deviation over each window of n values, n >= 2. len - n + 1 values.
+pub fn rolling_std_dev(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    Some(
+        rolling_variance(n, series)?
+            .into_iter()
+            .map(|v| v.sqrt().unwrap_or_default())
+            .collect(),
+    )
+}
+
+// ----------------------------------------------------------------------------- oscillators
+
+fn hundred() -> Decimal {
+    Decimal::from(100)
+}
+
+/// Relative strength index with Wilder's smoothing: the first average gain / loss is a plain mean over
+/// n changes, after that avg = (prev * (n - 1) + current) / n. 0..100, len - n values.
+pub fn rsi(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    if n == 0 || n >= series.len() {
+        return None;
+    }
+    let changes: Vec<Decimal> = series.windows(2).map(|w| w[1] - w[0]).collect();
+    let gain = |c: &Decimal| (*c).max(Decimal::zero());
+    let loss = |c: &Decimal| (-*c).max(Decimal::zero());
+    let n_ = Decimal::from(n);
+    let mut avg_gain = changes[..n].iter().map(gain).sum::<Decimal>() / n_;
+    let mut avg_loss = changes[..n].iter().map(loss).sum::<Decimal>() / n_;
+    let rsi_of = |avg_gain: Decimal, avg_loss: Decimal| {
+        if avg_loss.is_zero() {
+            // no down moves at all - flat counts as neutral
+            return if avg_gain.is_zero() {
+                Decimal::from(50)
+            } else {
+                hundred()
+            };
+        }
+        hundred() - hundred() / (Decimal::one() + avg_gain / avg_loss)
+    };
+    let mut rsis = Vec::with_capacity(changes.len() - n + 1);
+    rsis.push(rsi_of(avg_gain, avg_loss));
+    for c in &changes[n..] {
+        avg_gain = (avg_gain * (n_ - Decimal::one()) + gain(c)) / n_;
+        avg_loss = (avg_loss * (n_ - Decimal::one()) + loss(c)) / n_;
+        rsis.push(rsi_of(avg_gain, avg_loss));
+    }
+    Some(rsis)
+}
+
+/// Where the close sits in the high-low range of the last n bars, 0 = at the lowest low, 100 = at the
+/// highest high. A range with no width counts as the middle. Uses the bars' own (unadjusted) prices.
+fn range_position(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    if n == 0 || n > quotes.len() {
+        return None;
+    }
+    let mut lows = RollingMinMax::new(n).on(PriceField::Low);
+    let mut highs = RollingMinMax::new(n).on(PriceField::High);
+    Some(
+        quotes
+            .iter()
+            .filter_map(|q| {
+                // both get every bar, warmed up or not
+                let (lows, highs) = (lows.update(q), highs.update(q));
+                let ((lowest, _), (_, highest)) = (lows?, highs?);
+                let range = highest - lowest;
+                Some(if range.is_zero() {
+                    Decimal::from(50)
+                } else {
+                    hundred() * (q.close - lowest) / range
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Stochastic %K over n bars from high / low / close. len - n + 1 values.
+pub fn stochastic_k(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    range_position(n, quotes)
+}
+
+/// Stochastic (%K, %D) - %D is the d bar sma of %K. %K has len - n + 1 values, %D d - 1 fewer.
+pub fn stochastic(n: usize, d: usize, quotes: &[YQuote]) -> Option<(Vec<Decimal>, Vec<Decimal>)> {
+    let k = stochastic_k(n, quotes)?;
+    let d = n_window_sma(d, &k)?;
+    Some((k, d))
+}
+
+/// Williams %R over n bars, -100 = close at the lowest low, 0 = at the highest high. len - n + 1 values.
+pub fn williams_r(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    Some(
+        range_position(n, quotes)?
+            .into_iter()
+            .map(|k| k - hundred())
+            .collect(),
+    )
+}
+
+/// Rate of change in % against the value n bars back. len - n values.
+pub fn roc(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
+    if n == 0 || n >= series.len() {
+        return None;
+    }
+    Some(
+        series
+            .iter()
+            .zip(&series[n..])
+            .map(|(then, now)| {
+                // same trick as price_diff against dividing by 0
+                let then = if then.is_zero() {
+                    Decimal::one()
+                } else {
+                    *then
+                };
+                hundred() * (*now - then) / then
+            })
+            .collect(),
+    )
+}
+
+// ----------------------------------------------------------------------------- macd / bollinger
+
+/// MACD over one price series. Each part lines up with the end of the input, the signal and histogram
+/// being shorter than the line by their own warm-up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Macd {
+    /// ema(fast) - ema(slow), len - slow + 1 values
+    pub line: Vec<Decimal>,
+    /// ema(signal) of the line
+    pub signal: Vec<Decimal>,
+    /// line - signal
+    pub histogram: Vec<Decimal>,
+}
+
+/// The usual settings are 12 / 26 / 9. None when there aren't enough values for a single signal value.
+pub fn macd(fast: usize, slow: usize, signal: usize, series: &[Decimal]) -> Option<Macd> {
+    let line = combine(
+        Decimal::one(),
+        &ema(fast, series)?,
+        Decimal::from(-1),
+        &ema(slow, series)?,
+    );
+    let signal = ema(signal, &line)?;
+    let histogram = combine(Decimal::one(), &line, Decimal::from(-1), &signal);
+    Some(Macd {
+        line,
+        signal,
+        histogram,
+    })
+}
+
+/// Bollinger bands over one price series, every part len - n + 1 values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BollingerBands {
+    /// n bar sma
+    pub middle: Vec<Decimal>,
+    /// middle + k standard deviations
+    pub upper: Vec<Decimal>,
+    pub lower: Vec<Decimal>,
+    /// where the price sits between the bands, 0 = lower, 1 = upper (can go past either)
+    pub percent_b: Vec<Decimal>,
+    /// (upper - lower) / middle
+    pub bandwidth: Vec<Decimal>,
+}
+
+/// Bands k population standard deviations around the n bar sma - the usual settings are 20 / 2.
+pub fn bollinger(n: usize, k: Decimal, series: &[Decimal]) -> Option<BollingerBands> {
+    if n < 2 || n > series.len() {
+        return None;
+    }
+    let middle = n_window_sma(n, series)?;
+    let widths: Vec<Decimal> = run(RollingVariance::new(n).population(), series)
+        .into_iter()
+        .map(|v| k * v.sqrt().unwrap_or_default())
+        .collect();
+    let upper: Vec<Decimal> = middle.iter().zip(&widths).map(|(m, w)| m + w).collect();
+    let lower: Vec<Decimal> = middle.iter().zip(&widths).map(|(m, w)| m - w).collect();
+    let prices = &series[n - 1..];
+    let percent_b = prices
+        .iter()
+        .zip(upper.iter().zip(&lower))
+        .map(|(price, (upper, lower))| {
+            let width = upper - lower;
+            if width.is_zero() {
+                // flat window - the price is the middle
+                Decimal::new(5, 1)
+            } else {
+                (price - lower) / width
+            }
+        })
+        .collect();
+    let bandwidth = middle
+        .iter()
+        .zip(upper.iter().zip(&lower))
+        .map(|(middle, (upper, lower))| {
+            if middle.is_zero() {
+                Decimal::zero()
+            } else {
+                (upper - lower) / middle
+            }
+        })
+        .collect();
+    Some(BollingerBands {
+        middle,
+        upper,
+        lower,
+        percent_b,
+        bandwidth,
+    })
+}
+
+// ----------------------------------------------------------------------------- ranges / volatility
+
+/// True range of every bar - the widest of high - low and the gaps from the previous close. The first
+/// bar has no previous close so it's just high - low. Same length as the input.
+pub fn true_range(quotes: &[YQuote]) -> Vec<Decimal> {
+    let mut prev_close: Option<Decimal> = None;
+    quotes
+        .iter()
+        .map(|q| {
+            let range = q.high - q.low;
+            let tr = match prev_close {
+                Some(prev) => range.max((q.high - prev).abs()).max((q.low - prev).abs()),
+                None => range,
+            };
+            prev_close = Some(q.close);
+            tr
+        })
+        .collect()
+}
+
+/// Average true range with Wilder's smoothing, seeded with the mean of the first n true ranges.
+/// len - n + 1 values.
+pub fn atr(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    if n == 0 || n > quotes.len() {
+        return None;
+    }
+    let trs = true_range(quotes);
+    let n_ = Decimal::from(n);
+    let mut atrs = Vec::with_capacity(trs.len() - n + 1);
+    atrs.push(trs[..n].iter().sum::<Decimal>() / n_);
+    for tr in &trs[n..] {
+        let prev = atrs[atrs.len() - 1];
+        atrs.push((prev * (n_ - Decimal::one()) + tr) / n_);
+    }
+    Some(atrs)
+}
+
+/// A band around a price, every part the same length.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub middle: Vec<Decimal>,
+    pub upper: Vec<Decimal>,
+    pub lower: Vec<Decimal>,
+}
+
+/// Keltner channel - the n bar ema of the close, k atr(atr_n) either side. The usual settings are
+/// 20 / 2 / 10. As long as the shorter of the two warm-ups allows.
+pub fn keltner(n: usize, k: Decimal, atr_n: usize, quotes: &[YQuote]) -> Option<Channel> {
+    let middle = ema(n, &PriceField::Close.series(quotes))?;
+    let widths = atr(atr_n, quotes)?;
+    let len = middle.len().min(widths.len());
+    let middle = middle[middle.len() - len..].to_vec();
+    let widths = &widths[widths.len() - len..];
+    Some(Channel {
+        upper: middle.iter().zip(widths).map(|(m, w)| m + k * w).collect(),
+        lower: middle.iter().zip(widths).map(|(m, w)| m - k * w).collect(),
+        middle,
+    })
+}
+
+/// Donchian channel - the highest high and lowest low of the last n bars, middle halfway. len - n + 1 values.
+pub fn donchian(n: usize, quotes: &[YQuote]) -> Option<Channel> {
+    if n == 0 || n > quotes.len() {
+        return None;
+    }
+    let mut lows = RollingMinMax::new(n).on(PriceField::Low);
+    let mut highs = RollingMinMax::new(n).on(PriceField::High);
+    let (mut upper, mut lower) = (vec![], vec![]);
+    for q in quotes {
+        if let (Some((lowest, _)), Some((_, highest))) = (lows.update(q), highs.update(q)) {
+            upper.push(highest);
+            lower.push(lowest);
+        }
+    }
+    Some(Channel {
+        middle: upper
+            .iter()
+            .zip(&lower)
+            .map(|(u, l)| (u + l) / Decimal::from(2))
+            .collect(),
+        upper,
+        lower,
+    })
+}
+
+/// ln of a price ratio. Goes through f64 - rust_decimal's ln is only good to a couple of digits near 1,
+/// which is exactly where bar to bar ratios live. Non-positive ratios (bad ticks) count as no move.
+pub(crate) fn ln_ratio(num: Decimal, den: Decimal) -> Decimal {
+    if num <= Decimal::zero() || den <= Decimal::zero() {
+        return Decimal::zero();
+    }
+    (num / den)
+        .to_f64()
+        .and_then(|r| Decimal::from_f64(r.ln()))
+        .unwrap_or_default()
+}
+
+/// sqrt of the n bar mean of a per-bar variance estimate
+fn rolling_estimator(n: usize, per_bar: Vec<Decimal>) -> Option<Vec<Decimal>> {
+    Some(
+        n_window_sma(n, &per_bar)?
+            .into_iter()
+            .map(|v| v.max(Decimal::zero()).sqrt().unwrap_or_default())
+            .collect(),
+    )
+}
+
+/// Parkinson volatility over n bars from high / low only, per bar - multiply by sqrt(bars per year) to
+/// annualise. len - n + 1 values.
+pub fn parkinson(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    if n == 0 {
+        return None;
+    }
+    // 1 / (4 ln 2)
+    let scale = Decimal::from_f64(1.0 / (4.0 * 2f64.ln())).unwrap_or_default();
+    let per_bar = quotes
+        .iter()
+        .map(|q| {
+            let hl = ln_ratio(q.high, q.low);
+            scale * hl * hl
+        })
+        .collect();
+    rolling_estimator(n, per_bar)
+}
+
+/// Garman-Klass volatility over n bars from the full ohlc bar, per bar like `parkinson`. len - n + 1 values.
+pub fn garman_klass(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    if n == 0 {
+        return None;
+    }
+    // 2 ln 2 - 1
+    let co_weight = Decimal::from_f64(2.0 * 2f64.ln() - 1.0).unwrap_or_default();
+    let per_bar = quotes
+        .iter()
+        .map(|q| {
+            let hl = ln_ratio(q.high, q.low);
+            let co = ln_ratio(q.close, q.open);
+            hl * hl / Decimal::from(2) - co_weight * co * co
+        })
+        .collect();
+    rolling_estimator(n, per_bar)
+}
+
+// ----------------------------------------------------------------------------- volume
+
+/// (high + low + close) / 3 of every bar
+pub fn typical_price(quotes: &[YQuote]) -> Vec<Decimal> {
+    quotes
+        .iter()
+        .map(|q| (q.high + q.low + q.close) / Decimal::from(3))
+        .collect()
+}
+
+/// Volume weighted average of the typical price since the first bar, one value per bar - slice the bars
+/// to move the anchor. Until any volume has traded it's just the typical price.
+pub fn anchored_vwap(quotes: &[YQuote]) -> Vec<Decimal> {
+    let (mut value, mut volume) = (Decimal::zero(), Decimal::zero());
+    typical_price(quotes)
+        .into_iter()
+        .zip(quotes)
+        .map(|(tp, q)| {
+            value += tp * Decimal::from(q.volume);
+            volume += Decimal::from(q.volume);
+            if volume.is_zero() {
+                tp
+            } else {
+                value / volume
+            }
+        })
+        .collect()
+}
+
+/// Volume weighted average of the typical price over the last n bars. len - n + 1 values.
+pub fn rolling_vwap(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    if n == 0 || n > quotes.len() {
+        return None;
+    }
+    let tps = typical_price(quotes);
+    let values: Vec<Decimal> = tps
+        .iter()
+        .zip(quotes)
+        .map(|(tp, q)| tp * Decimal::from(q.volume))
+        .collect();
+    let volumes = PriceField::Volume.series(quotes);
+    // sma ratios are the sum ratios, the n cancels
+    let value_avgs = n_window_sma(n, &values)?;
+    let volume_avgs = n_window_sma(n, &volumes)?;
+    Some(
+        value_avgs
+            .iter()
+            .zip(&volume_avgs)
+            .zip(&tps[n - 1..])
+            .map(|((value, volume), tp)| {
+                if volume.is_zero() {
+                    *tp
+                } else {
+                    value / volume
+                }
+            })
+            .collect(),
+    )
+}
+
+/// On-balance volume - running total adding the bar's volume on an up close and taking it off on a
+/// down one. Starts at 0 on the first bar, same length as the input.
+pub fn obv(quotes: &[YQuote]) -> Vec<Decimal> {
+    let mut total = Decimal::zero();
+    let mut prev_close: Option<Decimal> = None;
+    quotes
+        .iter()
+        .map(|q| {
+            let volume = Decimal::from(q.volume);
+            match prev_close {
+                Some(prev) if q.close > prev => total += volume,
+                Some(prev) if q.close < prev => total -= volume,
+                _ => {}
+            }
+            prev_close = Some(q.close);
+            total
+        })
+        .collect()
+}
+
+/// Money flow index over n bars - an rsi of typical price times volume. 0..100, len - n values.
+pub fn mfi(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    if n == 0 || n >= quotes.len() {
+        return None;
+    }
+    let tps = typical_price(quotes);
+    // (positive, negative) money flow of every bar after the first
+    let flows: Vec<(Decimal, Decimal)> = tps
+        .windows(2)
+        .zip(&quotes[1..])
+        .map(|(tp, q)| {
+            let flow = tp[1] * Decimal::from(q.volume);
+            match tp[1].cmp(&tp[0]) {
+                std::cmp::Ordering::Greater => (flow, Decimal::zero()),
+                std::cmp::Ordering::Less => (Decimal::zero(), flow),
+                std::cmp::Ordering::Equal => (Decimal::zero(), Decimal::zero()),
+            }
+        })
+        .collect();
+    Some(
+        flows
+            .windows(n)
+            .map(|w| {
+                let positive: Decimal = w.iter().map(|(p, _)| *p).sum();
+                let negative: Decimal = w.iter().map(|(_, n)| *n).sum();
+                if negative.is_zero() {
+                    return if positive.is_zero() {
+                        Decimal::from(50)
+                    } else {
+                        hundred()
+                    };
+                }
+                hundred() - hundred() / (Decimal::one() + positive / negative)
+            })
+            .collect(),
+    )
+}
+
+/// Accumulation / distribution line - running total of volume weighted by where the close sits in the
+/// bar's range, -1 at the low to +1 at the high. Same length as the input.
+pub fn accumulation_distribution(quotes: &[YQuote]) -> Vec<Decimal> {
+    let mut total = Decimal::zero();
+    quotes
+        .iter()
+        .map(|q| {
+            let range = q.high - q.low;
+            if !range.is_zero() {
+                let clv = ((q.close - q.low) - (q.high - q.close)) / range;
+                total += clv * Decimal::from(q.volume);
+            }
+            total
+        })
+        .collect()
+}
+
+/// Each bar's volume against the average of the n bars before it, 1 = a normal day. len - n values.
+pub fn relative_volume(n: usize, quotes: &[YQuote]) -> Option<Vec<Decimal>> {
+    if n == 0 || n >= quotes.len() {
+        return None;
+    }
+    let volumes = PriceField::Volume.series(quotes);
+    let avgs = n_window_sma(n, &volumes[..volumes.len() - 1])?;
+    Some(
+        volumes[n..]
+            .iter()
+            .zip(&avgs)
+            .map(|(volume, avg)| {
+                if avg.is_zero() {
+                    Decimal::zero()
+                } else {
+                    volume / avg
+                }
+            })
+            .collect(),
+    )
+}
+
+// ----------------------------------------------------------------------------- columns
+
+/// An indicator that can be picked as extra output columns, eg `--indicators ema:20,hma:16,stoch:14:3`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Indicator {
+    Sma(usize),
+    Ema(usize),
+    Wma(usize),
+    Dema(usize),
+    Tema(usize),
+    Hma(usize),
+    Rsi(usize),
+    /// %K period, %D period
+    Stochastic(usize, usize),
+    WilliamsR(usize),
+    Roc(usize),
+    /// fast, slow, signal
+    Macd(usize, usize, usize),
+    /// period, standard deviations
+    Bollinger(usize, Decimal),
+    Atr(usize),
+    /// ema period, atr multiple, atr period
+    Keltner(usize, Decimal, usize),
+    Donchian(usize),
+    Parkinson(usize),
+    GarmanKlass(usize),
+    /// anchored at the first bar of the range
+    Vwap,
+    RollingVwap(usize),
+    Obv,
+    Mfi(usize),
+    AccumulationDistribution,
+    RelativeVolume(usize),
+}
+
+impl Indicator {
+    /// Column names, one per series `compute` gives back.
+    pub fn columns(&self) -> Vec<String> {
+        let parts: &[&str] = match self {
+            Indicator::Stochastic(..) => &["%k", "%d"],
+            Indicator::Macd(..) => &["line", "signal", "hist"],
+            Indicator::Bollinger(..) => &["mid", "upper", "lower", "%b", "width"],
+            Indicator::Keltner(..) | Indicator::Donchian(..) => &["mid", "upper", "lower"],
+            _ => return vec![self.to_string()],
+        };
+        parts.iter().map(|p| format!("{} {}", self, p)).collect()
+    }
+
+    /// The indicator over the whole history, warm-up dropped, one series per column. `series` is the price
+    /// the single series indicators run on - the range and volume ones read the (unadjusted) bars.
+    pub fn compute(&self, quotes: &[YQuote], series: &[Decimal]) -> Vec<Option<Vec<Decimal>>> {
+        match *self {
+            Indicator::Sma(n) => vec![n_window_sma(n, series)],
+            Indicator::Ema(n) => vec![ema(n, series)],
+            Indicator::Wma(n) => vec![wma(n, series)],
+            Indicator::Dema(n) => vec![dema(n, series)],
+            Indicator::Tema(n) => vec![tema(n, series)],
+            Indicator::Hma(n) => vec![hma(n, series)],
+            Indicator::Rsi(n) => vec![rsi(n, series)],
+            Indicator::Stochastic(n, d) => {
+                let k = stochastic_k(n, quotes);
+                let d = k.as_deref().and_then(|k| n_window_sma(d, k));
+                vec![k, d]
+            }
+            Indicator::WilliamsR(n) => vec![williams_r(n, quotes)],
+            Indicator::Roc(n) => vec![roc(n, series)],
+            Indicator::Macd(fast, slow, signal) => match macd(fast, slow, signal, series) {
+                Some(m) => vec![Some(m.line), Some(m.signal), Some(m.histogram)],
+                None => vec![None; 3],
+            },
+            Indicator::Bollinger(n, k) => match bollinger(n, k, series) {
+                Some(b) => vec![
+                    Some(b.middle),
+                    Some(b.upper),
+                    Some(b.lower),
+                    Some(b.percent_b),
+                    Some(b.bandwidth),
+                ],
+                None => vec![None; 5],
+            },
+            Indicator::Atr(n) => vec![atr(n, quotes)],
+            Indicator::Keltner(n, k, atr_n) => channel_columns(keltner(n, k, atr_n, quotes)),
+            Indicator::Donchian(n) => channel_columns(donchian(n, quotes)),
+            Indicator::Parkinson(n) => vec![parkinson(n, quotes)],
+            Indicator::GarmanKlass(n) => vec![garman_klass(n, quotes)],
+            Indicator::Vwap => vec![Some(anchored_vwap(quotes))],
+            Indicator::RollingVwap(n) => vec![rolling_vwap(n, quotes)],
+            Indicator::Obv => vec![Some(obv(quotes))],
+            Indicator::Mfi(n) => vec![mfi(n, quotes)],
+            Indicator::AccumulationDistribution => vec![Some(accumulation_distribution(quotes))],
+            Indicator::RelativeVolume(n) => vec![relative_volume(n, quotes)],
+        }
+    }
+}
+
+fn channel_columns(channel: Option<Channel>) -> Vec<Option<Vec<Decimal>>> {
+    match channel {
+        Some(c) => vec![Some(c.middle), Some(c.upper), Some(c.lower)],
+        None => vec![None; 3],
+    }
+}
+
+impl fmt::Display for Indicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Indicator::Sma(n) => write!(f, "sma:{}", n),
+            Indicator::Ema(n) => write!(f, "ema:{}", n),
+            Indicator::Wma(n) => write!(f, "wma:{}", n),
+            Indicator::Dema(n) => write!(f, "dema:{}", n),
+            Indicator::Tema(n) => write!(f, "tema:{}", n),
+            Indicator::Hma(n) => write!(f, "hma:{}", n),
+            Indicator::Rsi(n) => write!(f, "rsi:{}", n),
+            Indicator::Stochastic(n, d) => write!(f, "stoch:{}:{}", n, d),
+            Indicator::WilliamsR(n) => write!(f, "willr:{}", n),
+            Indicator::Roc(n) => write!(f, "roc:{}", n),
+            Indicator::Macd(fast, slow, signal) => write!(f, "macd:{}:{}:{}", fast, slow, signal),
+            Indicator::Bollinger(n, k) => write!(f, "bb:{}:{}", n, k.normalize()),
+            Indicator::Atr(n) => write!(f, "atr:{}", n),
+            Indicator::Keltner(n, k, atr_n) => write!(f, "kc:{}:{}:{}", n, k.normalize(), atr_n),
+            Indicator::Donchian(n) => write!(f, "dc:{}", n),
+            Indicator::Parkinson(n) => write!(f, "parkinson:{}", n),
+            Indicator::GarmanKlass(n) => write!(f, "gk:{}", n),
+            Indicator::Vwap => write!(f, "vwap"),
+            Indicator::RollingVwap(n) => write!(f, "vwap:{}", n),
+            Indicator::Obv => write!(f, "obv"),
+            Indicator::Mfi(n) => write!(f, "mfi:{}", n),
+            Indicator::AccumulationDistribution => write!(f, "ad"),
+            Indicator::RelativeVolume(n) => write!(f, "rvol:{}", n),
+        }
+    }
+}
+
+impl FromStr for Indicator {
+    type Err = String;
+
+    /// "<name>:<param>[:<param>...]", eg "ema:20", "stoch:14:3" or "bb:20:2.5"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || {
+            format!(
+                "unknown indicator '{}' - use sma, ema, wma, dema, tema, hma, rsi, willr, roc, atr, dc, parkinson, \
+                 gk, mfi or rvol with a period (eg ema:20), stoch:<k>[:<d>], macd[:<fast>:<slow>:<signal>], \
+                 bb[:<period>[:<std devs>]], kc[:<period>[:<atr multiple>[:<atr period>]]], vwap[:<period>], obv or ad",
+                s
+            )
+        };
+        let mut parts = s.trim().split(':');
+        let name = parts.next().unwrap_or_default().to_lowercase();
+        let mut periods: Vec<&str> = parts.collect();
+        // the bands' width multiple is the only parameter that isn't a period
+        let width = match name.as_str() {
+            "bb" | "kc" if periods.len() > 1 => {
+                Some(Decimal::from_str(periods.remove(1)).map_err(|_| bad())?)
+            }
+            _ => None,
+        };
+        let periods = periods
+            .iter()
+            .map(|p| p.parse::<usize>().map_err(|_| bad()))
+            .collect::<Result<Vec<usize>, String>>()?;
+        if periods.contains(&0) {
+            return Err(format!("indicator period has to be at least 1 in '{}'", s));
+        }
+        match (name.as_str(), periods.as_slice()) {
+            ("sma", [n]) => Ok(Indicator::Sma(*n)),
+            ("ema", [n]) => Ok(Indicator::Ema(*n)),
+            ("wma", [n]) => Ok(Indicator::Wma(*n)),
+            ("dema", [n]) => Ok(Indicator::Dema(*n)),
+            ("tema", [n]) => Ok(Indicator::Tema(*n)),
+            ("hma", [n]) => Ok(Indicator::Hma(*n)),
+            ("rsi", [n]) => Ok(Indicator::Rsi(*n)),
+            ("stoch", [n]) => Ok(Indicator::Stochastic(*n, 3)),
+            ("stoch", [n, d]) => Ok(Indicator::Stochastic(*n, *d)),
+            ("willr", [n]) => Ok(Indicator::WilliamsR(*n)),
+            ("roc", [n]) => Ok(Indicator::Roc(*n)),
+            ("macd", []) => Ok(Indicator::Macd(12, 26, 9)),
+            ("macd", [fast, slow, signal]) if fast < slow => {
+                Ok(Indicator::Macd(*fast, *slow, *signal))
+            }
+            ("macd", [_, _, _]) => Err(format!(
+                "macd's fast period has to be below its slow one in '{}'",
+                s
+            )),
+            ("bb", []) => Ok(Indicator::Bollinger(20, Decimal::from(2))),
+            ("bb", [n]) => Ok(Indicator::Bollinger(
+                *n,
+                width.unwrap_or_else(|| Decimal::from(2)),
+            )),
+            ("atr", [n]) => Ok(Indicator::Atr(*n)),
+            ("kc", []) => Ok(Indicator::Keltner(20, Decimal::from(2), 10)),
+            ("kc", [n]) => Ok(Indicator::Keltner(
+                *n,
+                width.unwrap_or_else(|| Decimal::from(2)),
+                10,
+            )),
+            ("kc", [n, atr_n]) => Ok(Indicator::Keltner(
+                *n,
+                width.unwrap_or_else(|| Decimal::from(2)),
+                *atr_n,
+            )),
+            ("dc", [n]) => Ok(Indicator::Donchian(*n)),
+            ("parkinson", [n]) => Ok(Indicator::Parkinson(*n)),
+            ("gk", [n]) => Ok(Indicator::GarmanKlass(*n)),
+            ("vwap", []) => Ok(Indicator::Vwap),
+            ("vwap", [n]) => Ok(Indicator::RollingVwap(*n)),
+            ("obv", []) => Ok(Indicator::Obv),
+            ("mfi", [n]) => Ok(Indicator::Mfi(*n)),
+            ("ad", []) => Ok(Indicator::AccumulationDistribution),
+            ("rvol", [n]) => Ok(Indicator::RelativeVolume(*n)),
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// An indicator plus the bar field it runs on - None = the (adjusted) price the rest of the row uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndicatorColumn {
+    pub indicator: Indicator,
+    pub field: Option<PriceField>,
+}
+
+impl IndicatorColumn {
+    pub fn columns(&self) -> Vec<String> {
+        match self.field {
+            Some(field) => self
+                .indicator
+                .columns()
+                .into_iter()
+                .map(|c| format!("{}@{}", c, field))
+                .collect(),
+            None => self.indicator.columns(),
+        }
+    }
+
+    /// like Indicator::compute, with `adjusted` standing in when there's no field
+    pub fn compute(&self, quotes: &[YQuote], adjusted: &[Decimal]) -> Vec<Option<Vec<Decimal>>> {
+        match self.field {
+            Some(field) => self.indicator.compute(quotes, &field.series(quotes)),
+            None => self.indicator.compute(quotes, adjusted),
+        }
+    }
+}
+
+impl From<Indicator> for IndicatorColumn {
+    fn from(indicator: Indicator) -> Self {
+        Self {
+            indicator,
+            field: None,
+        }
+    }
+}
+
+impl fmt::Display for IndicatorColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field {
+            Some(field) => write!(f, "{}@{}", self.indicator, field),
+            None => write!(f, "{}", self.indicator),
+        }
+    }
+}
+
+impl FromStr for IndicatorColumn {
+    type Err = String;
+
+    /// "<indicator>[@<field>]", eg "macd@close" or "bb:20:2@high"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (indicator, field) = match s.trim().split_once('@') {
+            Some((indicator, field)) => (indicator, Some(field.parse()?)),
+            None => (s, None),
+        };
+        Ok(Self {
+            indicator: indicator.parse()?,
+            field,
+        })
+    }
+}
+
+/// Comma separated list of indicators, as taken on the command line.
+pub fn parse_indicators(s: &str) -> Result<Vec<IndicatorColumn>, String> {
+    s.split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(IndicatorColumn::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn decimals(s: &str) -> Vec<Decimal> {
+        s.split_whitespace()
+            .map(|v| Decimal::from_str(v).unwrap())
+            .collect()
+    }
+
+    /// each value within `tolerance` of the expected one
+    fn assert_close(got: &[Decimal], expected: &[Decimal], tolerance: &str) {
+        let tolerance = Decimal::from_str(tolerance).unwrap();
+        assert_eq!(got.len(), expected.len(), "{:?} vs {:?}", got, expected);
+        for (i, (g, e)) in got.iter().zip(expected).enumerate() {
+            assert!((g - e).abs() <= tolerance, "value {}: {} vs {}", i, g, e);
+        }
+    }
+
+    /// daily bars from (high, low, close)
+    fn bars(hlc: &[(i64, i64, i64)]) -> Vec<YQuote> {
+        hlc.iter()
+            .enumerate()
+            .map(|(i, (h, l, c))| YQuote {
+                timestamp: Utc.ymd(2021, 1, 4).and_hms(21, 0, 0) + Duration::days(i as i64),
+                open: Decimal::from(*c),
+                high: Decimal::from(*h),
+                low: Decimal::from(*l),
+                volume: 1000,
+                close: Decimal::from(*c),
+                adjclose: Decimal::from(*c),
+            })
+            .collect()
+    }
+
+    // stockcharts' RSI worked example (ChartSchool, "Relative Strength Index") - closes and 14 day RSI.
+    // their spreadsheet rounds along the way, so it's a few hundredths off the exact figures.
+    const RSI_CLOSES: &str =
+        "44.34 44.09 44.15 43.61 44.33 44.83 45.10 45.42 45.84 46.08 45.89 46.03 \
+        45.61 46.28 46.28 46.00 46.03 46.41 46.22 45.64 46.21 46.25 45.71 46.45 45.78 45.35 44.03 \
+        44.18 44.22 44.57 43.42 42.66 43.13";
+    const RSI_14: &str = "70.53 66.32 66.55 69.41 66.36 57.97 62.93 63.26 56.06 62.38 54.71 50.42 \
+        39.99 41.46 41.87 45.46 37.30 33.08 37.77";
+
+    #[test]
+    fn rsi_matches_the_stockcharts_table() {
+        let rsi = rsi(14, &decimals(RSI_CLOSES)).unwrap();
+        assert_close(&rsi, &decimals(RSI_14), "0.1");
+    }
+
+    #[test]
+    fn rsi_of_a_one_way_series() {
+        let up = decimals("1 2 3 4 5");
+        assert_eq!(rsi(3, &up).unwrap(), vec![Decimal::from(100); 2]);
+        let flat = decimals("5 5 5 5");
+        assert_eq!(rsi(2, &flat).unwrap(), vec![Decimal::from(50); 2]);
+        assert_eq!(rsi(4, &up[..4]), None);
+    }
+
+    // worked by hand: 3 bar window, %k = 100 * (close - lowest low) / (highest high - lowest low)
+    //   bars 1-3: hh 12, ll 8, close 11 -> 75
+    //   bars 2-4: hh 12, ll 9, close 10 -> 33.33
+    //   bars 3-5: hh 13, ll 9, close 13 -> 100
+    fn stoch_bars() -> Vec<YQuote> {
+        bars(&[
+            (10, 8, 9),
+            (11, 9, 10),
+            (12, 9, 11),
+            (12, 10, 10),
+            (13, 11, 13),
+        ])
+    }
+
+    #[test]
+    fn stochastic_by_hand() {
+        let (k, d) = stochastic(3, 2, &stoch_bars()).unwrap();
+        assert_close(&k, &decimals("75 33.3333 100"), "0.0001");
+        // %d = 2 bar average of %k
+        assert_close(&d, &decimals("54.1667 66.6667"), "0.0001");
+        assert_eq!(stochastic(6, 2, &stoch_bars()), None);
+    }
+
+    #[test]
+    fn williams_r_by_hand() {
+        let r = williams_r(3, &stoch_bars()).unwrap();
+        assert_close(&r, &decimals("-25 -66.6667 0"), "0.0001");
+    }
+
+    #[test]
+    fn flat_range_is_the_middle() {
+        let flat = bars(&[(5, 5, 5), (5, 5, 5)]);
+        assert_eq!(stochastic_k(2, &flat).unwrap(), vec![Decimal::from(50)]);
+    }
+
+    #[test]
+    fn roc_by_hand() {
+        // (11 - 9) / 9, (10 - 10) / 10, (13 - 11) / 11
+        let roc = roc(2, &decimals("9 10 11 10 13")).unwrap();
+        assert_close(&roc, &decimals("22.2222 0 18.1818"), "0.0001");
+        assert_eq!(super::roc(5, &decimals("9 10 11 10 13")), None);
+    }
+
+    // stockcharts' moving average worked example (ChartSchool, "Moving Averages - Simple and Exponential")
+    // - closes and the 10 day EMA, seeded with the 10 day SMA
+    const MA_CLOSES: &str = "22.27 22.19 22.08 22.17 22.18 22.13 22.23 22.43 22.24 22.29 22.15 22.39 \
+        22.38 22.61 23.36 24.05 23.75 23.83 23.95 23.63 23.82 23.87 23.65 23.19 23.10 23.33 22.68 23.10 \
+        22.40 22.17";
+    const EMA_10: &str = "22.22 22.21 22.24 22.27 22.33 22.52 22.80 22.97 23.13 23.28 23.34 23.43 \
+        23.51 23.54 23.47 23.40 23.39 23.26 23.23 23.08 22.92";
+
+    #[test]
+    fn ema_matches_the_stockcharts_table() {
+        let ema = ema(10, &decimals(MA_CLOSES)).unwrap();
+        assert_close(&ema, &decimals(EMA_10), "0.01");
+        assert_eq!(super::ema(31, &decimals(MA_CLOSES)), None);
+    }
+
+    // worked by hand: weights 1, 2, 3 over a total of 6
+    //   (1 + 4 + 9) / 6, (2 + 6 + 12) / 6, (3 + 8 + 15) / 6, then (4 + 10 + 3) / 6 on the dip
+    #[test]
+    fn wma_by_hand() {
+        let wma = wma(3, &decimals("1 2 3 4 5 1")).unwrap();
+        let sixth = |x: i64| Decimal::from(x) / Decimal::from(6);
+        assert_eq!(wma, vec![sixth(14), sixth(20), sixth(26), sixth(17)]);
+        assert_eq!(super::wma(0, &decimals("1 2")), None);
+    }
+
+    #[test]
+    fn output_lengths_match_the_docs() {
+        // 30 closes, n = 10 (and 9 for the hull, so sqrt(n) is whole)
+        let closes = decimals(MA_CLOSES);
+        assert_eq!(ema(10, &closes).unwrap().len(), 30 - 10 + 1);
+        assert_eq!(wma(10, &closes).unwrap().len(), 30 - 10 + 1);
+        assert_eq!(dema(10, &closes).unwrap().len(), 30 - 2 * 9);
+        assert_eq!(tema(10, &closes).unwrap().len(), 30 - 3 * 9);
+        assert_eq!(hma(9, &closes).unwrap().len(), 30 - 8 - 2);
+        // one short of a single value
+        assert_eq!(dema(10, &closes[..18]), None);
+        assert_eq!(tema(10, &closes[..27]), None);
+        assert_eq!(tema(10, &closes[..28]).unwrap().len(), 1);
+    }
+
+    // On a straight line the ema seeded with an sma lags by exactly (n - 1) / 2 bars, and the wma by
+    // (n - 1) / 3 - dema, tema and hull(9) are built to cancel that out, so they land on the line.
+    #[test]
+    fn lag_free_averages_track_a_straight_line() {
+        let line: Vec<Decimal> = (1..=40).map(Decimal::from).collect();
+        let tail = |len: usize| line[line.len() - len..].to_vec();
+        let ema = ema(5, &line).unwrap();
+        assert_close(
+            &ema,
+            &tail(36)
+                .iter()
+                .map(|x| x - Decimal::from(2))
+                .collect::<Vec<_>>(),
+            "0.000001",
+        );
+        let dema = dema(5, &line).unwrap();
+        assert_close(&dema, &tail(dema.len()), "0.000001");
+        let tema = tema(5, &line).unwrap();
+        assert_close(&tema, &tail(tema.len()), "0.000001");
+        let hma = hma(9, &line).unwrap();
+        assert_close(&hma, &tail(hma.len()), "0.000001");
+    }
+
+    // macd(5, 10, 4) and bollinger(20, 2) over the moving average closes, worked independently in python
+    // with exact fractions and statistics.pstdev, rounded to 4 places
+    const MACD_LINE: &str =
+        "0.0474 0.0209 0.0415 0.0487 0.0845 0.2126 0.3741 0.3941 0.3932 0.3871 \
+        0.3118 0.2806 0.2542 0.1910 0.0753 -0.0060 -0.0152 -0.1177 -0.1029 -0.1946 -0.2677";
+    const MACD_SIGNAL: &str =
+        "0.0396 0.0576 0.1196 0.2214 0.2904 0.3315 0.3538 0.3370 0.3144 0.2903 \
+        0.2506 0.1805 0.1059 0.0575 -0.0126 -0.0487 -0.1071 -0.1713";
+    const MACD_HIST: &str =
+        "0.0091 0.0269 0.0930 0.1527 0.1036 0.0616 0.0333 -0.0252 -0.0338 -0.0361 \
+        -0.0596 -0.1052 -0.1119 -0.0726 -0.1051 -0.0542 -0.0875 -0.0964";
+    const BB_MIDDLE: &str =
+        "22.7155 22.7930 22.8770 22.9555 23.0065 23.0525 23.1125 23.1350 23.1685 23.1765 23.1705";
+    const BB_UPPER: &str =
+        "24.1261 24.2661 24.3939 24.4617 24.4714 24.4676 24.4665 24.4438 24.4371 24.4234 24.4355";
+    const BB_LOWER: &str =
+        "21.3049 21.3199 21.3601 21.4493 21.5416 21.6374 21.7585 21.8262 21.8999 21.9296 21.9055";
+    const BB_PERCENT_B: &str =
+        "0.8242 0.8486 0.8273 0.7305 0.5626 0.5168 0.5803 0.3262 0.4730 0.1886 0.1045";
+    const BB_WIDTH: &str =
+        "0.1242 0.1293 0.1326 0.1312 0.1273 0.1228 0.1172 0.1131 0.1095 0.1076 0.1092";
+
+    #[test]
+    fn macd_matches_the_reference() {
+        let macd = macd(5, 10, 4, &decimals(MA_CLOSES)).unwrap();
+        assert_close(&macd.line, &decimals(MACD_LINE), "0.0001");
+        assert_close(&macd.signal, &decimals(MACD_SIGNAL), "0.0001");
+        assert_close(&macd.histogram, &decimals(MACD_HIST), "0.0001");
+        // 30 closes are too few for the usual 12 / 26 / 9 - 26 + 9 - 1 = 34 needed
+        assert_eq!(super::macd(12, 26, 9, &decimals(MA_CLOSES)), None);
+    }
+
+    #[test]
+    fn bollinger_matches_the_reference() {
+        let bands = bollinger(20, Decimal::from(2), &decimals(MA_CLOSES)).unwrap();
+        assert_close(&bands.middle, &decimals(BB_MIDDLE), "0.0001");
+        assert_close(&bands.upper, &decimals(BB_UPPER), "0.0001");
+        assert_close(&bands.lower, &decimals(BB_LOWER), "0.0001");
+        assert_close(&bands.percent_b, &decimals(BB_PERCENT_B), "0.0001");
+        assert_close(&bands.bandwidth, &decimals(BB_WIDTH), "0.0001");
+
+        // a flat window has no width, and the price sits in the middle
+        let flat = bollinger(3, Decimal::from(2), &decimals("5 5 5")).unwrap();
+        assert_eq!(flat.upper, flat.lower);
+        assert_eq!(flat.percent_b, decimals("0.5"));
+        assert_eq!(
+            super::bollinger(1, Decimal::from(2), &decimals("5 5 5")),
+            None
+        );
+    }
+
+    fn column(s: &str) -> IndicatorColumn {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn field_after_the_at() {
+        assert_eq!(
+            column("macd@close"),
+            IndicatorColumn {
+                indicator: Indicator::Macd(12, 26, 9),
+                field: Some(PriceField::Close)
+            }
+        );
+        assert_eq!(
+            column(" bb:20:2.5@High"),
+            IndicatorColumn {
+                indicator: Indicator::Bollinger(20, Decimal::new(25, 1)),
+                field: Some(PriceField::High)
+            }
+        );
+        assert_eq!(column("ema:20"), Indicator::Ema(20).into());
+        assert_eq!(column("bb@low").columns()[0], "bb:20:2 mid@low".to_string());
+        assert_eq!(column("rsi:14@volume").to_string(), "rsi:14@volume");
+        assert!("ema:20@bid".parse::<IndicatorColumn>().is_err());
+        assert!("ema@close".parse::<IndicatorColumn>().is_err());
+
+        let columns = parse_indicators("ema:20, macd@close,").unwrap();
+        assert_eq!(columns.len(), 2);
+    }
+
+    #[test]
+    fn field_picks_the_series() {
+        let quotes = bars(&[(12, 8, 10), (13, 9, 11)]);
+        let adjusted = decimals("1 2");
+        let sma = |s: &str| column(s).compute(&quotes, &adjusted)[0].clone().unwrap();
+        assert_eq!(sma("sma:2"), decimals("1.5"));
+        assert_eq!(sma("sma:2@high"), decimals("12.5"));
+        assert_eq!(sma("sma:2@volume"), decimals("1000"));
+    }
+
+    // worked by hand, 3 bars with Wilder's smoothing:
+    //   true ranges 2, 2, 3, 3, 6 (gap up from 9 to a 12 low), 3 (the low is 3 under the previous close)
+    //   atr: (2 + 2 + 3) / 3 = 7/3, then (7/3 * 2 + 3) / 3 = 23/9, (23/9 * 2 + 6) / 3 = 100/27, 281/81
+    fn ranges() -> Vec<YQuote> {
+        bars(&[
+            (10, 8, 9),
+            (11, 9, 10),
+            (12, 9, 11),
+            (11, 8, 9),
+            (15, 12, 14),
+            (13, 11, 12),
+        ])
+    }
+
+    fn fraction(num: i64, den: i64) -> Decimal {
+        Decimal::from(num) / Decimal::from(den)
+    }
+
+    #[test]
+    fn true_range_and_atr_by_hand() {
+        assert_eq!(true_range(&ranges()), decimals("2 2 3 3 6 3"));
+        let atr = atr(3, &ranges()).unwrap();
+        let expected = [
+            fraction(7, 3),
+            fraction(23, 9),
+            fraction(100, 27),
+            fraction(281, 81),
+        ];
+        assert_close(&atr, &expected, "0.000000000001");
+        assert_eq!(super::atr(7, &ranges()), None);
+    }
+
+    #[test]
+    fn donchian_by_hand() {
+        // highs 10 11 12 11 15 13, lows 8 9 9 8 12 11 - the 8 on the 4th bar holds the floor
+        let channel = donchian(3, &ranges()).unwrap();
+        assert_eq!(channel.upper, decimals("12 12 15 15"));
+        assert_eq!(channel.lower, decimals("8 8 8 8"));
+        assert_eq!(channel.middle, decimals("10 10 11.5 11.5"));
+    }
+
+    #[test]
+    fn keltner_by_hand() {
+        // ema(3) of the closes: seeded at 10, then halfway to each new close - 9.5, 11.75, 11.875
+        let channel = keltner(3, Decimal::from(2), 3, &ranges()).unwrap();
+        assert_eq!(channel.middle, decimals("10 9.5 11.75 11.875"));
+        let atr = atr(3, &ranges()).unwrap();
+        let offset = |sign: i64| -> Vec<Decimal> {
+            channel
+                .middle
+                .iter()
+                .zip(&atr)
+                .map(|(m, a)| m + Decimal::from(2 * sign) * a)
+                .collect()
+        };
+        assert_eq!(channel.upper, offset(1));
+        assert_eq!(channel.lower, offset(-1));
+    }
+
+    #[test]
+    fn range_volatility() {
+        // every bar's high is twice its low: parkinson = sqrt(ln(2)^2 / (4 ln 2)) = sqrt(ln 2) / 2
+        let flat = bars(&[(2, 1, 1), (4, 2, 2), (6, 3, 3)]);
+        let ln2 = 2f64.ln();
+        let expect = |v: f64| vec![Decimal::from_f64(v).unwrap(); 2];
+        assert_close(
+            &parkinson(2, &flat).unwrap(),
+            &expect(ln2.sqrt() / 2.0),
+            "0.000001",
+        );
+        // garman-klass with open = close is just the range term, sqrt(ln(2)^2 / 2)
+        assert_close(
+            &garman_klass(2, &flat).unwrap(),
+            &expect(ln2 / 2f64.sqrt()),
+            "0.000001",
+        );
+        // and a close that doubles the open takes (2 ln 2 - 1) ln(2)^2 off that
+        let up: Vec<YQuote> = flat
+            .iter()
+            .map(|q| YQuote {
+                open: q.low,
+                close: q.high,
+                ..q.clone()
+            })
+            .collect();
+        let gk = (ln2 * ln2 * (1.5 - 2.0 * ln2)).sqrt();
+        assert_close(&garman_klass(2, &up).unwrap(), &expect(gk), "0.000001");
+        assert_eq!(parkinson(4, &flat), None);
+    }
+
+    /// daily bars from (high, low, close, volume)
+    fn volume_bars(hlcv: &[(i64, i64, i64, u64)]) -> Vec<YQuote> {
+        let mut quotes = bars(
+            &hlcv
+                .iter()
+                .map(|(h, l, c, _)| (*h, *l, *c))
+                .collect::<Vec<_>>(),
+        );
+        for (q, (_, _, _, v)) in quotes.iter_mut().zip(hlcv) {
+            q.volume = *v;
+        }
+        quotes
+    }
+
+    // typical prices 10, 12, 12, 8, 10 - the second bar traded nothing
+    fn volume_day() -> Vec<YQuote> {
+        volume_bars(&[
+            (12, 9, 9, 100),
+            (13, 10, 13, 0),
+            (14, 11, 11, 300),
+            (10, 7, 7, 200),
+            (11, 8, 11, 400),
+        ])
+    }
+
+    #[test]
+    fn vwap_by_hand() {
+        // running sum of typical price * volume over running volume:
+        // 1000 / 100, still 1000 / 100, 4600 / 400, 6200 / 600, 10200 / 1000
+        assert_eq!(
+            anchored_vwap(&volume_day()),
+            vec![
+                Decimal::from(10),
+                Decimal::from(10),
+                Decimal::new(115, 1),
+                fraction(31, 3),
+                Decimal::new(102, 1)
+            ]
+        );
+        // no volume yet - the typical price
+        assert_eq!(anchored_vwap(&volume_day()[1..2]), decimals("12"));
+
+        // 1000 / 100, 3600 / 300, 5200 / 500, 5600 / 600
+        let rolling = rolling_vwap(2, &volume_day()).unwrap();
+        assert_close(
+            &rolling,
+            &[
+                Decimal::from(10),
+                Decimal::from(12),
+                Decimal::new(104, 1),
+                fraction(28, 3),
+            ],
+            "0.000000000001",
+        );
+        let nothing_traded = volume_bars(&[(13, 10, 13, 0), (14, 11, 11, 0)]);
+        assert_eq!(rolling_vwap(2, &nothing_traded).unwrap(), decimals("12"));
+    }
+
+    #[test]
+    fn obv_by_hand() {
+        // closes 9 13 11 7 11 - the up day on no volume adds nothing
+        assert_eq!(obv(&volume_day()), decimals("0 0 -300 -500 -100"));
+        let flat = volume_bars(&[(2, 1, 1, 100), (2, 1, 1, 100)]);
+        assert_eq!(obv(&flat), decimals("0 0"));
+    }
+
+    #[test]
+    fn mfi_by_hand() {
+        // flows after the first bar: up on no volume (0), unchanged (nothing), down 8 * 200, up 10 * 400
+        //   2 bar windows: nothing either way -> 50, all down -> 0, 100 - 100 / (1 + 4000 / 1600)
+        let mfi = mfi(2, &volume_day()).unwrap();
+        assert_close(
+            &mfi,
+            &[Decimal::from(50), Decimal::zero(), fraction(500, 7)],
+            "0.000000000001",
+        );
+        assert_eq!(super::mfi(5, &volume_day()), None);
+    }
+
+    #[test]
+    fn accumulation_distribution_by_hand() {
+        // closes at the low (-1), nothing traded, at the low twice more, then at the high (+1)
+        assert_eq!(
+            accumulation_distribution(&volume_day()),
+            decimals("-100 -100 -400 -600 -200")
+        );
+        // a bar with no range doesn't move it
+        let no_range = volume_bars(&[(12, 8, 10, 100), (10, 10, 10, 500)]);
+        assert_eq!(accumulation_distribution(&no_range), decimals("0 0"));
+    }
+
+    #[test]
+    fn relative_volume_is_against_the_bars_before() {
+        // volumes 100 0 300 200 400: 300 / avg(100, 0), 200 / avg(0, 300), 400 / avg(300, 200)
+        let rvol = relative_volume(2, &volume_day()).unwrap();
+        assert_close(
+            &rvol,
+            &[Decimal::from(6), fraction(4, 3), Decimal::new(16, 1)],
+            "0.000000000001",
+        );
+        // nothing traded before it - no average to compare with
+        let quiet = volume_bars(&[(2, 1, 1, 0), (2, 1, 1, 0), (2, 1, 1, 100)]);
+        assert_eq!(relative_volume(2, &quiet).unwrap(), decimals("0"));
+        assert_eq!(relative_volume(5, &volume_day()), None);
+    }
+}
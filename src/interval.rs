@@ -0,0 +1,220 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Bar size. Everything from the cli down to the provider talks in these rather than raw strings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Interval {
+    OneMinute,
+    FiveMinutes,
+    OneHour,
+    #[default]
+    OneDay,
+    OneWeek,
+    OneMonth,
+}
+
+impl Interval {
+    /// the string yahoo expects in the `interval=` query param
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OneMinute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::OneHour => "1h",
+            Interval::OneDay => "1d",
+            Interval::OneWeek => "1wk",
+            Interval::OneMonth => "1mo",
+        }
+    }
+
+    /// minute and hour bars - anything shorter than a session
+    pub fn is_intraday(&self) -> bool {
+        matches!(
+            self,
+            Interval::OneMinute | Interval::FiveMinutes | Interval::OneHour
+        )
+    }
+
+    /// How far back yahoo keeps bars of this size. None = full history.
+    pub fn max_lookback(&self) -> Option<Duration> {
+        match self {
+            Interval::OneMinute => Some(Duration::days(30)),
+            Interval::FiveMinutes => Some(Duration::days(60)),
+            Interval::OneHour => Some(Duration::days(730)),
+            _ => None,
+        }
+    }
+
+    /// Longest range yahoo will serve in a single request. None = no limit.
+    pub fn max_request_span(&self) -> Option<Duration> {
+        match self {
+            Interval::OneMinute => Some(Duration::days(7)),
+            Interval::FiveMinutes => Some(Duration::days(60)),
+            Interval::OneHour => Some(Duration::days(730)),
+            _ => None,
+        }
+    }
+
+    /// Bars in a year of trading - 252 sessions of 6.5h (9:30-16:00), hourly bars start on the half hour so there's 7 of them.
+    pub fn bars_per_year(&self) -> f64 {
+        match self {
+            Interval::OneMinute => 252.0 * 390.0,
+            Interval::FiveMinutes => 252.0 * 78.0,
+            Interval::OneHour => 252.0 * 7.0,
+            Interval::OneDay => 252.0,
+            Interval::OneWeek => 52.0,
+            Interval::OneMonth => 12.0,
+        }
+    }
+
+    /// How many bars cover a span of calendar time, eg 30 calendar days = 21 daily bars. Never less than 1.
+    pub fn bars_in(&self, span: Duration) -> usize {
+        let years = span.num_seconds() as f64 / (365.25 * 24.0 * 60.0 * 60.0);
+        ((years * self.bars_per_year()).round() as usize).max(1)
+    }
+
+    pub fn validate(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if from > to {
+            return Err(format!("from ({}) is after to ({})", from, to));
+        }
+        if let Some(lookback) = self.max_lookback() {
+            if from < now - lookback {
+                return Err(format!(
+                    "{} bars only go back {} days, {} is too far back",
+                    self,
+                    lookback.num_days(),
+                    from.date()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits from..to into consecutive ranges no longer than max_request_span.
+    pub fn chunks(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let span = match self.max_request_span() {
+            Some(span) => span,
+            None => return vec![(from, to)],
+        };
+        let mut chunks = vec![];
+        let mut start = from;
+        while start < to {
+            let end = std::cmp::min(start + span, to);
+            chunks.push((start, end));
+            start = end;
+        }
+        if chunks.is_empty() {
+            chunks.push((from, to));
+        }
+        chunks
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "1m" => Ok(Interval::OneMinute),
+            "5m" => Ok(Interval::FiveMinutes),
+            "1h" | "60m" => Ok(Interval::OneHour),
+            "1d" => Ok(Interval::OneDay),
+            "1wk" => Ok(Interval::OneWeek),
+            "1mo" => Ok(Interval::OneMonth),
+            _ => Err(format!(
+                "unsupported interval '{}' - use 1m, 5m, 1h, 1d, 1wk or 1mo",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(d: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 3, d).and_hms(0, 0, 0)
+    }
+
+    #[test]
+    fn chunks_cover_the_range_back_to_back() {
+        let chunks = Interval::OneMinute.chunks(at(1), at(20));
+        assert_eq!(
+            chunks,
+            vec![(at(1), at(8)), (at(8), at(15)), (at(15), at(20))]
+        );
+        // one chunk when there's no limit, or the range fits
+        assert_eq!(
+            Interval::OneDay.chunks(at(1), at(20)),
+            vec![(at(1), at(20))]
+        );
+        assert_eq!(
+            Interval::FiveMinutes.chunks(at(1), at(20)),
+            vec![(at(1), at(20))]
+        );
+        // and even for an empty range
+        assert_eq!(
+            Interval::OneMinute.chunks(at(1), at(1)),
+            vec![(at(1), at(1))]
+        );
+    }
+
+    #[test]
+    fn bars_in_calendar_time() {
+        assert_eq!(Interval::OneDay.bars_in(Duration::days(30)), 21);
+        assert_eq!(Interval::OneDay.bars_in(Duration::days(365)), 252);
+        assert_eq!(Interval::OneWeek.bars_in(Duration::days(30)), 4);
+        assert_eq!(Interval::FiveMinutes.bars_in(Duration::days(1)), 54);
+        // never 0
+        assert_eq!(Interval::OneMonth.bars_in(Duration::days(1)), 1);
+    }
+
+    #[test]
+    fn validate_checks_order_and_lookback() {
+        let now = at(31);
+        assert!(Interval::OneDay.validate(at(1), at(20), now).is_ok());
+        assert!(Interval::OneDay.validate(at(20), at(1), now).is_err());
+        // 1m only goes back 30 days
+        assert!(Interval::OneMinute.validate(at(1), at(20), now).is_ok());
+        assert!(Interval::OneMinute
+            .validate(at(1) - Duration::seconds(1), at(20), now)
+            .is_err());
+        assert!(Interval::OneDay
+            .validate(Utc.ymd(1990, 1, 1).and_hms(0, 0, 0), at(20), now)
+            .is_ok());
+    }
+
+    #[test]
+    fn parses_what_it_prints() {
+        for i in [
+            Interval::OneMinute,
+            Interval::FiveMinutes,
+            Interval::OneHour,
+            Interval::OneDay,
+            Interval::OneWeek,
+            Interval::OneMonth,
+        ] {
+            assert_eq!(i.to_string().parse::<Interval>(), Ok(i));
+        }
+        assert_eq!("60m".parse::<Interval>(), Ok(Interval::OneHour));
+        assert!("2d".parse::<Interval>().is_err());
+    }
+}
@@ -1,2 +1,21 @@
+pub mod adjustment;
+pub mod benchmark;
+pub mod calendar;
+pub mod cleaning;
+pub mod corporate_actions;
+pub mod correlation;
+pub mod csv_provider;
 pub mod download_data;
+pub mod drawdown;
+pub mod fx;
+pub mod indicators;
+pub mod interval;
+pub mod metadata;
+pub mod price_frame;
 pub mod process_data;
+pub mod quote_cache;
+pub mod rate_limit;
+pub mod retry;
+pub mod risk;
+pub mod streaming;
+pub mod timezone;
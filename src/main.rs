@@ -1,18 +1,40 @@
 use std::io;
 
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use clap::Clap;
-use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
 
 use async_std::prelude::*;
 use async_std::stream;
-use xactor::{message, Actor, Broker, Context, Handler, Result, Service, Supervisor};
+use xactor::{message, Actor, Broker, Context, Handler, Result, Service};
 
-use future_finance_labs::download_data::fetch_stonks_data;
-use future_finance_labs::process_data::{
-    extract_adjclose, min_and_max, n_window_sma, price_diff, process_data, Data,
+use future_finance_labs::adjustment::Adjustment;
+use future_finance_labs::benchmark::{Benchmark, BenchmarkStats};
+use future_finance_labs::calendar::{self, TradingCalendar};
+use future_finance_labs::cleaning::{CleaningPolicy, MissingPolicy, OutlierFilter};
+use future_finance_labs::correlation::{rolling_correlation, CorrelationMatrix};
+use future_finance_labs::csv_provider::{ColumnMapping, CsvConfig, CsvProvider, DecimalFormat};
+use future_finance_labs::download_data::{
+    fetch_stonks_data, FallbackProvider, FetchError, History, QuoteProvider, YahooProvider,
 };
+use future_finance_labs::drawdown::DrawdownStats;
+use future_finance_labs::fx::FxConverter;
+use future_finance_labs::indicators::IndicatorColumn;
+use future_finance_labs::interval::Interval;
+use future_finance_labs::metadata::TickerInfo;
+use future_finance_labs::price_frame::{Join, PriceFrame};
+use future_finance_labs::process_data::{process_data, Data, ProcessConfig};
+use future_finance_labs::quote_cache::{CachedProvider, QuoteCache};
+use future_finance_labs::rate_limit::{RateLimitConfig, RateLimitedProvider, RateLimiter};
+use future_finance_labs::retry::{RetryPolicy, RetryingProvider};
+use future_finance_labs::risk::{RiskConfig, RiskStats};
+use future_finance_labs::timezone::OutputZone;
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 //simpler but defo lacking functionality vs normal builder pattern
@@ -35,37 +57,516 @@ struct Opts {
     ///Date in yyyy-mm-dd format. Default = now.
     #[clap(short, long, default_value = "x")]
     to: String,
+    ///Bar size: 1m, 5m, 1h, 1d, 1wk or 1mo.
+    #[clap(short, long, default_value = "1d")]
+    interval: Interval,
+    ///Price series to analyse: vendor (yahoo's adjclose), raw, split, total or total-additive (rebuilt from close + dividends/splits).
+    #[clap(long, default_value = "vendor")]
+    adjustment: Adjustment,
+    ///Print how far our rebuilt adjustment is from the vendor's adjclose. Needs --adjustment split, total or total-additive.
+    #[clap(long)]
+    validate_adjustment: bool,
+    ///Bars further from the vendor's adjclose than this fraction get listed by --validate-adjustment (0.005 = 0.5%).
+    #[clap(long, default_value = "0.005")]
+    adjustment_tolerance: Decimal,
+    ///What to do with bars that have zero/missing prices: drop, ffill or interpolate. Default = ffill.
+    #[clap(long)]
+    clean: Option<MissingPolicy>,
+    ///Skip cleaning altogether and use the data as downloaded.
+    #[clap(long, conflicts_with = "clean")]
+    no_clean: bool,
+    ///Flag bad ticks: jump:<fraction> (eg jump:0.5) or zscore:<sigmas> (eg zscore:5).
+    #[clap(long)]
+    outliers: Option<OutlierFilter>,
+    ///Treat flagged outliers like missing bars instead of only reporting them.
+    #[clap(long)]
+    remove_outliers: bool,
+    ///Trading calendar to use for every ticker: NYSE, NASDAQ, LSE, XETRA (or a yahoo exchange code). Default = guess from the ticker suffix.
+    #[clap(long, parse(try_from_str = calendar_by_name))]
+    exchange: Option<&'static TradingCalendar>,
+    ///Convert every price into this currency (eg USD) at each day's fx rate before analysing.
+    #[clap(long)]
+    report_currency: Option<String>,
+    ///Dir of <PAIR>.csv rate files (eg EURUSD=X.csv with Date and Close columns) for pairs the source doesn't have.
+    #[clap(long)]
+    fx_csv: Option<String>,
+    ///Extra columns with the latest value of each indicator, eg "ema:20,rsi:14,stoch:14:3,macd:12:26:9,bb:20:2,atr:14,kc:20:2:10,dc:20,gk:20,vwap,mfi:14,obv,rvol:20". Add @<field> (open, high, low, close, adjclose, volume) to run one on something other than the adjusted price, eg macd@close.
+    #[clap(long, require_delimiter = true)]
+    indicators: Vec<IndicatorColumn>,
+    ///Dir to also write each ticker's full indicator series to, as <TICKER>.csv with one row per bar.
+    #[clap(long)]
+    series_dir: Option<String>,
+    ///Add return and risk columns: annualised return and volatility, rolling volatility, sharpe, sortino, skew, kurtosis and 5th/95th percentile returns.
+    #[clap(long)]
+    risk: bool,
+    ///Annual risk-free rate for sharpe and sortino, as a fraction (0.04 = 4%).
+    #[clap(long, default_value = "0")]
+    risk_free_rate: Decimal,
+    ///Bars in the rolling volatility window. Default = 30 calendar days' worth.
+    #[clap(long)]
+    vol_window: Option<usize>,
+    ///Add max drawdown columns (depth, peak/trough/recovery dates, longest drawdown, calmar) and list the N deepest drawdowns.
+    #[clap(long)]
+    drawdowns: Option<usize>,
+    ///Dir to write each run's cross-ticker return correlation and covariance matrices to (csv and json). The correlation matrix is printed too.
+    #[clap(long)]
+    matrix_dir: Option<String>,
+    ///Pairs to write rolling return correlations for, eg "AAPL:MSFT,SPY:TLT". Needs --matrix-dir.
+    #[clap(long, require_delimiter = true, parse(try_from_str = parse_pair))]
+    corr_pairs: Vec<(String, String)>,
+    ///Bars in the rolling correlation window. Default = 30 calendar days' worth.
+    #[clap(long)]
+    corr_window: Option<usize>,
+    ///Compare every ticker against this one (eg SPY): beta, alpha, correlation, r2, tracking error, information ratio and up/down capture. Fetched once per run.
+    #[clap(long)]
+    benchmark: Option<String>,
+    ///Zone for intraday bar times in the output: exchange, utc, or an IANA name like Asia/Tokyo. Daily bars always show their trading date.
+    #[clap(long, default_value = "exchange")]
+    output_tz: OutputZone,
+    ///Keep polling while the market is closed - by default tickers are only re-fetched during their session.
+    #[clap(long)]
+    always_poll: bool,
+    ///Where quotes come from: "yahoo", or "csv:/path" for a dir of <TICKER>.csv files or a single file with a symbol column.
+    #[clap(long, default_value = "yahoo")]
+    source: Source,
+    ///Csv column overrides, eg "date=Timestamp,close=Last". Defaults match a yahoo export.
+    #[clap(long)]
+    csv_columns: Option<ColumnMapping>,
+    ///Csv date format (chrono syntax), or "unix" for epoch seconds.
+    #[clap(long, default_value = "%Y-%m-%d")]
+    csv_date_format: String,
+    ///Zone the csv's dates are in, eg America/New_York.
+    #[clap(long, default_value = "UTC")]
+    csv_timezone: Tz,
+    ///Currency the csv prices are in, eg USD or GBp. Needed for --report-currency.
+    #[clap(long)]
+    csv_currency: Option<String>,
+    ///Csv uses the european layout - ";" delimiter, "," decimals, "." thousands.
+    #[clap(long)]
+    csv_decimal_comma: bool,
+    ///Max requests per hour to the yahoo api.
+    #[clap(long, default_value = "2000")]
+    hourly_limit: NonZeroU32,
+    ///Max requests per day to the yahoo api.
+    #[clap(long, default_value = "48000")]
+    daily_limit: NonZeroU32,
+    ///How many times to retry a download after a network error or rate limit.
+    #[clap(long, default_value = "4")]
+    max_retries: u32,
+    ///Fail the download if yahoo sends a quote that won't convert (NaN, inf) instead of skipping that row.
+    #[clap(long)]
+    strict_quotes: bool,
+    ///Where downloaded quotes are kept, so only missing ranges get fetched.
+    #[clap(long, default_value = "cache")]
+    cache_dir: String,
+    ///Always download the full range, don't read or write the cache.
+    #[clap(long)]
+    no_cache: bool,
+    ///Serve purely from the cache, never hit the network. Only for the yahoo source.
+    #[clap(long, conflicts_with = "no-cache")]
+    offline: bool,
+    #[clap(subcommand)]
+    cmd: Option<Command>,
+}
+
+/// Where quotes come from.
+enum Source {
+    Yahoo,
+    /// dir of <TICKER>.csv files, or one file with a symbol column
+    Csv(PathBuf),
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("csv", path)) if !path.is_empty() => Ok(Source::Csv(PathBuf::from(path))),
+            _ if s == "yahoo" => Ok(Source::Yahoo),
+            _ => Err(format!("unknown source '{}' - use yahoo or csv:/path", s)),
+        }
+    }
+}
+
+fn calendar_by_name(name: &str) -> std::result::Result<&'static TradingCalendar, String> {
+    calendar::by_name(name).ok_or_else(|| format!("no trading calendar for exchange '{}'", name))
+}
+
+/// "AAPL:MSFT"
+fn parse_pair(pair: &str) -> std::result::Result<(String, String), String> {
+    match pair.trim().split_once(':') {
+        Some((a, b)) if !a.is_empty() && !b.is_empty() => Ok((a.to_uppercase(), b.to_uppercase())),
+        _ => Err(format!("bad pair '{}', expected eg AAPL:MSFT", pair)),
+    }
+}
+
+#[derive(Clap)]
+enum Command {
+    ///Inspect or clear the quote cache.
+    Cache(CacheCmd),
+    ///Find tickers by company name or partial symbol.
+    Search(SearchCmd),
+    ///Show name, exchange, currency, type and timezone for tickers.
+    Info(InfoCmd),
+}
+
+#[derive(Clap)]
+struct SearchCmd {
+    query: String,
+    #[clap(long, default_value = "10")]
+    limit: usize,
+}
+
+#[derive(Clap)]
+struct InfoCmd {
+    ///Comma separated, like --tickers.
+    tickers: String,
+}
+
+#[derive(Clap)]
+enum CacheCmd {
+    ///List what's cached per ticker + interval.
+    List(CacheFilter),
+    ///Delete cached quotes. Without filters wipes everything.
+    Purge(CacheFilter),
+}
+
+#[derive(Clap)]
+struct CacheFilter {
+    #[clap(long)]
+    ticker: Option<String>,
+    #[clap(long)]
+    interval: Option<Interval>,
+}
+
+impl Opts {
+    fn cleaning_policy(&self) -> Option<CleaningPolicy> {
+        if self.no_clean {
+            return None;
+        }
+        Some(CleaningPolicy {
+            missing: self.clean.unwrap_or(MissingPolicy::ForwardFill),
+            outliers: self.outliers,
+            remove_outliers: self.remove_outliers,
+            ..CleaningPolicy::default()
+        })
+    }
+
+    /// fx rates come from the same source as quotes, topped up from --fx-csv
+    fn fx_converter(&self, provider: Arc<dyn QuoteProvider>) -> Option<Arc<FxConverter>> {
+        let currency = self.report_currency.as_deref()?;
+        let provider: Arc<dyn QuoteProvider> = match &self.fx_csv {
+            Some(path) => {
+                // rate files only need a date and a close
+                let config = CsvConfig {
+                    columns: ColumnMapping {
+                        open: "Close".into(),
+                        high: "Close".into(),
+                        low: "Close".into(),
+                        ..ColumnMapping::default()
+                    },
+                    ..CsvConfig::default()
+                };
+                Arc::new(FallbackProvider::new(
+                    provider,
+                    Arc::new(CsvProvider::with_config(path, config)),
+                ))
+            }
+            None => provider,
+        };
+        Some(Arc::new(FxConverter::new(provider, currency)))
+    }
+
+    fn provider(&self) -> Arc<dyn QuoteProvider> {
+        match &self.source {
+            Source::Csv(path) => {
+                if self.offline {
+                    clap::Error::with_description(
+                        "--offline only applies to the yahoo source, csv files are always local\n"
+                            .into(),
+                        clap::ErrorKind::ArgumentConflict,
+                    )
+                    .exit();
+                }
+                let mut config = CsvConfig {
+                    columns: self.csv_columns.clone().unwrap_or_default(),
+                    date_format: self.csv_date_format.clone(),
+                    timezone: self.csv_timezone,
+                    currency: self.csv_currency.clone(),
+                    ..CsvConfig::default()
+                };
+                if self.csv_decimal_comma {
+                    config.delimiter = b';';
+                    config.decimal = DecimalFormat {
+                        decimal_separator: ',',
+                        thousands_separator: Some('.'),
+                    };
+                }
+                Arc::new(CsvProvider::with_config(path, config))
+            }
+            Source::Yahoo => {
+                let limiter = RateLimiter::new(RateLimitConfig {
+                    per_hour: Some(self.hourly_limit.get()),
+                    per_day: Some(self.daily_limit.get()),
+                });
+                let policy = RetryPolicy {
+                    max_retries: self.max_retries,
+                    ..RetryPolicy::default()
+                };
+                let yahoo = RetryingProvider::new(
+                    RateLimitedProvider::new(
+                        YahooProvider::new().skip_bad_rows(!self.strict_quotes),
+                        Arc::new(limiter),
+                    ),
+                    policy,
+                );
+                if self.no_cache {
+                    return Arc::new(yahoo);
+                }
+                Arc::new(
+                    CachedProvider::new(yahoo, QuoteCache::new(&self.cache_dir))
+                        .offline(self.offline),
+                )
+            }
+        }
+    }
+}
+
+fn run_cache_cmd(cache: QuoteCache, cmd: CacheCmd) {
+    let fmt_ts = |ts: Option<DateTime<Utc>>| ts.map_or("-".into(), |ts| ts.to_rfc3339());
+    match cmd {
+        CacheCmd::List(filter) => {
+            let mut wtr = csv::Writer::from_writer(io::stdout());
+            wtr.write_record([
+                "symbol",
+                "interval",
+                "bars",
+                "first",
+                "last",
+                "fetched ranges",
+                "dividends",
+                "splits",
+            ])
+            .unwrap();
+            for entry in cache.entries().unwrap() {
+                if !entry.matches(filter.ticker.as_deref(), filter.interval) {
+                    continue;
+                }
+                wtr.write_record(&[
+                    entry.ticker,
+                    entry.interval.to_string(),
+                    entry.bars.to_string(),
+                    fmt_ts(entry.first),
+                    fmt_ts(entry.last),
+                    entry.covered.len().to_string(),
+                    entry.dividends.to_string(),
+                    entry.splits.to_string(),
+                ])
+                .unwrap();
+            }
+            wtr.flush().unwrap();
+        }
+        CacheCmd::Purge(filter) => {
+            let removed = cache
+                .purge(filter.ticker.as_deref(), filter.interval)
+                .unwrap();
+            println!("purged {} cache entries", removed);
+        }
+    }
+}
+
+async fn run_search_cmd(provider: &dyn QuoteProvider, cmd: SearchCmd) {
+    let found = match provider.search(&cmd.query).await {
+        Ok(found) => found,
+        Err(e) => return println!("search failed: {}", e),
+    };
+    let mut wtr = csv::Writer::from_writer(io::stdout());
+    wtr.write_record(["symbol", "name", "exchange", "type"])
+        .unwrap();
+    for r in found.into_iter().take(cmd.limit) {
+        wtr.write_record(&[r.symbol, r.name, r.exchange, r.instrument_type])
+            .unwrap();
+    }
+    wtr.flush().unwrap();
+}
+
+async fn run_info_cmd(provider: &dyn QuoteProvider, cmd: InfoCmd) {
+    for ticker in cmd.tickers.split(',') {
+        match provider.info(ticker.trim()).await {
+            Ok(info) => println!("{}", info),
+            Err(e) => println!("{}: {}", ticker, e),
+        }
+    }
+}
+
+/// Only letters, digits and the punctuation yahoo uses: BRK-B, VOD.L, ^GSPC, EURUSD=X
+fn looks_like_ticker(ticker: &str) -> bool {
+    !ticker.is_empty()
+        && ticker
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".-^=".contains(c))
+}
+
+/// Checks every ticker before any downloading starts. Unknown ones are dropped with a few suggestions;
+/// if the source can't be reached we keep the ticker and let the download report it.
+async fn resolve_tickers(provider: &dyn QuoteProvider, tickers: &str) -> Vec<TickerInfo> {
+    let mut resolved = vec![];
+    for ticker in tickers.split(',').map(str::trim) {
+        if !looks_like_ticker(ticker) {
+            println!("SKIPPING '{}': not a valid ticker", ticker);
+            continue;
+        }
+        match provider.info(ticker).await {
+            Ok(info) => resolved.push(info),
+            Err(FetchError::NotFound(_)) => {
+                let suggestions: Vec<String> = provider
+                    .search(ticker)
+                    .await
+                    .unwrap_or_default()
+                    .into_iter()
+                    .take(3)
+                    .map(|r| r.symbol)
+                    .collect();
+                if suggestions.is_empty() {
+                    println!("SKIPPING {}: unknown ticker", ticker);
+                } else {
+                    println!(
+                        "SKIPPING {}: unknown ticker - did you mean {}?",
+                        ticker,
+                        suggestions.join(", ")
+                    );
+                }
+            }
+            Err(e) => {
+                println!("couldn't look up {}, carrying on: {}", ticker, e);
+                resolved.push(TickerInfo::unknown(ticker));
+            }
+        }
+    }
+    resolved
 }
 
 // ----------------------------------------------------------------------------- msg
 
+/// One pass of the polling loop - lets the aggregation stage tell when it has heard back about every ticker.
+#[derive(Clone, Copy, Debug)]
+struct Run {
+    id: u64,
+    tickers: usize,
+}
+
 #[message]
 #[derive(Clone, Debug)]
 struct DownloadMsg {
-    ticker: String,
+    info: TickerInfo,
     from: DateTime<Utc>,
     to: DateTime<Utc>,
+    config: ProcessConfig,
+    run: Run,
 }
 
 #[message]
 #[derive(Clone, Debug)]
 struct ProcessMsg {
-    data: Data,
-    ticker: String,
+    history: History,
+    info: TickerInfo,
+    config: ProcessConfig,
+    run: Run,
+}
+
+/// (symbol, adjusted bars, zone they're dated in)
+type Collected = (String, Data, Tz);
+
+/// A ticker's done for this run - None when it failed, so the run still completes.
+#[message]
+#[derive(Clone, Debug)]
+struct CollectMsg {
+    run: Run,
+    series: Option<Collected>,
 }
 
 // ----------------------------------------------------------------------------- actor
 
-#[derive(Default)]
-struct DownloadActor;
+struct DownloadActor {
+    provider: Arc<dyn QuoteProvider>,
+    /// None = leave prices in whatever currency they're quoted in
+    fx: Option<Arc<FxConverter>>,
+}
+
+impl DownloadActor {
+    fn new(provider: Arc<dyn QuoteProvider>, fx: Option<Arc<FxConverter>>) -> Self {
+        Self { provider, fx }
+    }
+}
 
 #[derive(Default)]
 struct ProcessActor;
 
+/// Waits for every ticker of a run, then does the cross-ticker numbers.
+struct AggregateActor {
+    dir: PathBuf,
+    /// (symbol, symbol) to write rolling correlations for
+    pairs: Vec<(String, String)>,
+    window: usize,
+    interval: Interval,
+    /// run id -> what's come in so far
+    pending: HashMap<u64, Vec<Option<Collected>>>,
+}
+
+impl AggregateActor {
+    fn new(dir: PathBuf, pairs: Vec<(String, String)>, window: usize, interval: Interval) -> Self {
+        Self {
+            dir,
+            pairs,
+            window,
+            interval,
+            pending: HashMap::new(),
+        }
+    }
+
+    fn report(&self, frame: &PriceFrame) -> std::io::Result<()> {
+        let matrix = CorrelationMatrix::compute(frame);
+        println!("return correlations:");
+        matrix.write_csv(&matrix.correlation, 2, io::stdout())?;
+        std::fs::create_dir_all(&self.dir)?;
+        let file = |name: &str| std::fs::File::create(self.dir.join(name));
+        matrix.write_csv(&matrix.correlation, 6, file("correlation.csv")?)?;
+        matrix.write_csv(&matrix.covariance, 8, file("covariance.csv")?)?;
+        serde_json::to_writer_pretty(file("matrix.json")?, &matrix.to_json())?;
+        for (a, b) in &self.pairs {
+            if frame.column(a).is_none() || frame.column(b).is_none() {
+                println!(
+                    "no data for {} / {}, skipping its rolling correlation",
+                    a, b
+                );
+                continue;
+            }
+            let mut wtr = csv::Writer::from_writer(file(&format!("rolling_{}_{}.csv", a, b))?);
+            wtr.write_record(["time", "correlation"])?;
+            for (key, corr) in rolling_correlation(self.window, frame, a, b) {
+                // daily keys are midnight on the trading date
+                let time = if self.interval.is_intraday() {
+                    key.to_string()
+                } else {
+                    key.date().to_string()
+                };
+                let corr = corr.map_or(String::new(), |c| c.round_dp(6).to_string());
+                wtr.write_record([time, corr])?;
+            }
+            wtr.flush()?;
+        }
+        println!(
+            "wrote correlation and covariance matrices to {}",
+            self.dir.display()
+        );
+        Ok(())
+    }
+}
+
 #[async_trait::async_trait]
 impl Actor for DownloadActor {
     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
-        ctx.subscribe::<DownloadMsg>().await;
+        ctx.subscribe::<DownloadMsg>().await?;
         Ok(())
     }
 }
@@ -73,21 +574,91 @@ impl Actor for DownloadActor {
 #[async_trait::async_trait]
 impl Actor for ProcessActor {
     async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
-        ctx.subscribe::<ProcessMsg>().await;
+        ctx.subscribe::<ProcessMsg>().await?;
         Ok(())
     }
 }
 
+#[async_trait::async_trait]
+impl Actor for AggregateActor {
+    async fn started(&mut self, ctx: &mut Context<Self>) -> Result<()> {
+        ctx.subscribe::<CollectMsg>().await?;
+        Ok(())
+    }
+}
+
+/// Fetches a ticker and converts it into the reporting currency if there is one. Failures are reported
+/// here and come back as None. The info's currency follows the conversion.
+async fn download(
+    provider: &dyn QuoteProvider,
+    fx: Option<&FxConverter>,
+    mut info: TickerInfo,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    interval: Interval,
+) -> Option<(History, TickerInfo)> {
+    let mut history =
+        match fetch_stonks_data(provider, info.symbol.clone(), from, to, interval).await {
+            Ok(history) => history,
+            // retries already happened inside the provider - report and move on to the next ticker
+            Err(e) => {
+                println!("FAILED downloading {}: {}", info.symbol, e);
+                return None;
+            }
+        };
+    if let Some(fx) = fx {
+        match info.currency.clone() {
+            Some(currency) => {
+                let timezone = info.timezone.unwrap_or(Tz::UTC);
+                history = match fx.convert(history, &currency, timezone).await {
+                    Ok(converted) => converted,
+                    Err(e) => {
+                        println!(
+                            "FAILED converting {} from {} to {}: {}",
+                            info.symbol,
+                            currency,
+                            fx.report_currency(),
+                            e
+                        );
+                        return None;
+                    }
+                };
+                info.currency = Some(fx.report_currency().to_string());
+            }
+            None => println!("{}: currency unknown, left unconverted", info.symbol),
+        }
+    }
+    Some((history, info))
+}
+
 #[async_trait::async_trait]
 impl Handler<DownloadMsg> for DownloadActor {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: DownloadMsg) {
-        let data = fetch_stonks_data(msg.ticker.clone(), msg.from, msg.to)
-            .await
-            .unwrap();
+        let (history, info) = match download(
+            &*self.provider,
+            self.fx.as_deref(),
+            msg.info,
+            msg.from,
+            msg.to,
+            msg.config.interval,
+        )
+        .await
+        {
+            Some(downloaded) => downloaded,
+            None => {
+                let _ = Broker::from_registry().await.unwrap().publish(CollectMsg {
+                    run: msg.run,
+                    series: None,
+                });
+                return;
+            }
+        };
         //once Download Actor finishes its work, it publishes a msg to the next q, which is the processing q, to be picked up by processing actors
         let _ = Broker::from_registry().await.unwrap().publish(ProcessMsg {
-            data,
-            ticker: msg.ticker,
+            history,
+            info,
+            config: msg.config,
+            run: msg.run,
         });
     }
 }
@@ -95,7 +666,51 @@ impl Handler<DownloadMsg> for DownloadActor {
 #[async_trait::async_trait]
 impl Handler<ProcessMsg> for ProcessActor {
     async fn handle(&mut self, _ctx: &mut Context<Self>, msg: ProcessMsg) {
-        process_data(msg.data, msg.ticker);
+        let processed = process_data(msg.history, &msg.info, &msg.config);
+        let symbol = msg.info.symbol;
+        let _ = Broker::from_registry().await.unwrap().publish(CollectMsg {
+            run: msg.run,
+            series: processed.map(|p| (symbol, p.data, p.timezone)),
+        });
+    }
+}
+
+/// Files a ticker's result under its run. Once every ticker of the run has reported back, the run is
+/// forgotten and the ones that came with data handed back.
+fn collect(
+    pending: &mut HashMap<u64, Vec<Option<Collected>>>,
+    msg: CollectMsg,
+) -> Option<Vec<Collected>> {
+    let collected = pending.entry(msg.run.id).or_default();
+    collected.push(msg.series);
+    if collected.len() < msg.run.tickers {
+        return None;
+    }
+    Some(
+        pending
+            .remove(&msg.run.id)
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .collect(),
+    )
+}
+
+#[async_trait::async_trait]
+impl Handler<CollectMsg> for AggregateActor {
+    async fn handle(&mut self, _ctx: &mut Context<Self>, msg: CollectMsg) {
+        let series = match collect(&mut self.pending, msg) {
+            Some(series) => series,
+            None => return,
+        };
+        // every pair gets matched up on the bars both have, so outer keeps the most
+        let frame = PriceFrame::join(self.interval, Join::Outer, series);
+        if frame.symbols().len() < 2 {
+            return println!("need at least 2 tickers with data for a correlation matrix");
+        }
+        if let Err(e) = self.report(&frame) {
+            println!("FAILED writing correlation matrices: {}", e);
+        }
     }
 }
 
@@ -103,52 +718,243 @@ impl Handler<ProcessMsg> for ProcessActor {
 
 #[xactor::main]
 async fn main() {
-    let opts = Opts::parse();
-    let from: DateTime<Utc> = opts
-        .from
-        .parse()
-        .unwrap_or(Utc::now() - chrono::Duration::days(60));
-    let to: DateTime<Utc> = opts.to.parse().unwrap_or(Utc::now());
+    let mut opts = Opts::parse();
+    match opts.cmd.take() {
+        Some(Command::Cache(cmd)) => return run_cache_cmd(QuoteCache::new(&opts.cache_dir), cmd),
+        Some(Command::Search(cmd)) => return run_search_cmd(&*opts.provider(), cmd).await,
+        Some(Command::Info(cmd)) => return run_info_cmd(&*opts.provider(), cmd).await,
+        None => {}
+    }
+    let now = Utc::now();
+    // 60 days back, or as far as the interval's bars go if that's less
+    let lookback = opts
+        .interval
+        .max_lookback()
+        .map_or(chrono::Duration::days(60), |max| {
+            max.min(chrono::Duration::days(60))
+        });
+    let from: DateTime<Utc> = opts.from.parse().unwrap_or(now - lookback);
+    let to: DateTime<Utc> = opts.to.parse().unwrap_or(now);
+    // checked the once - every download reuses the same range
+    if let Err(e) = opts.interval.validate(from, to, now) {
+        clap::Error::with_description(format!("{}\n", e), clap::ErrorKind::InvalidValue).exit();
+    }
 
-    let mut wtr = csv::Writer::from_writer(io::stdout());
-    wtr.write_record(&[
+    // raw keeps the vendor's adjclose, so it would only be compared against itself
+    if opts.validate_adjustment && matches!(opts.adjustment, Adjustment::Vendor | Adjustment::Raw) {
+        clap::Error::with_description(
+            "--validate-adjustment compares an --adjustment of our own against the vendor's, pick split, total or total-additive\n".into(),
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    let provider = opts.provider();
+    // bad tickers should fail here, not deep in the downloader
+    let tickers = resolve_tickers(&*provider, &opts.tickers).await;
+    if tickers.is_empty() {
+        return println!("no valid tickers, nothing to do");
+    }
+
+    let indicators = opts.indicators.clone();
+    let mut header: Vec<String> = [
         "period start",
         "symbol",
+        "currency",
+        "exchange",
         "price",
         "change %",
         "min",
         "max",
         "30d avg",
-    ])
-    .unwrap();
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect();
+    header.extend(indicators.iter().flat_map(|i| i.columns()));
+    if opts.risk {
+        header.extend(RiskStats::columns());
+    }
+    if opts.drawdowns.is_some() {
+        header.extend(DrawdownStats::columns());
+    }
+    let fx = opts.fx_converter(provider.clone());
+    // the benchmark only gets fetched the once, every ticker is compared against the same bars
+    let benchmark = match &opts.benchmark {
+        Some(symbol) => match resolve_tickers(&*provider, symbol).await.pop() {
+            Some(info) => download(&*provider, fx.as_deref(), info, from, to, opts.interval)
+                .await
+                .map(|(history, info)| {
+                    Arc::new(Benchmark {
+                        info,
+                        history,
+                        risk_free_rate: opts.risk_free_rate,
+                    })
+                }),
+            None => None,
+        },
+        None => None,
+    };
+    match (&opts.benchmark, &benchmark) {
+        (Some(symbol), None) => println!("no data for benchmark {}, carrying on without", symbol),
+        (_, Some(_)) => header.extend(BenchmarkStats::columns()),
+        _ => {}
+    }
+    let mut wtr = csv::Writer::from_writer(io::stdout());
+    wtr.write_record(&header).unwrap();
     wtr.flush().unwrap();
 
+    let config = ProcessConfig {
+        interval: opts.interval,
+        adjustment: opts.adjustment,
+        split_adjusted: provider.split_adjusted(),
+        validate_adjustment: opts
+            .validate_adjustment
+            .then_some(opts.adjustment_tolerance),
+        cleaning: opts.cleaning_policy(),
+        calendar: opts.exchange,
+        output_zone: opts.output_tz,
+        indicators,
+        series_dir: opts.series_dir.as_ref().map(PathBuf::from),
+        risk: opts.risk.then_some(RiskConfig {
+            risk_free_rate: opts.risk_free_rate,
+            window: opts.vol_window,
+        }),
+        drawdowns: opts.drawdowns,
+        benchmark,
+    };
+
     // weird: if you don't collect addresses, the program stalls
     // todo weird 2: if you start more than one actor - ALL of them get msgs
     //  if this can't be fixed this solution is actually WORSE than my solution with tokio actors...
     //  https://github.com/sunli829/xactor/issues/45
-    let _daddr = DownloadActor::start_default().await.unwrap();
-    // let _daddr2 = DownloadActor::start_default().await.unwrap();
+    let _daddr = DownloadActor::new(provider.clone(), fx)
+        .start()
+        .await
+        .unwrap();
+    // let _daddr2 = DownloadActor::new(provider.clone()).start().await.unwrap();
     let _paddr = ProcessActor::start_default().await.unwrap();
     // let _paddr2 = ProcessActor::start_default().await.unwrap();
+    let _aaddr = match &opts.matrix_dir {
+        Some(dir) => Some(
+            AggregateActor::new(
+                PathBuf::from(dir),
+                opts.corr_pairs.clone(),
+                opts.corr_window
+                    .unwrap_or_else(|| opts.interval.bars_in(chrono::Duration::days(30))),
+                opts.interval,
+            )
+            .start()
+            .await
+            .unwrap(),
+        ),
+        None => None,
+    };
 
     // their way - doesn't work for me, I don't see any msgs processed
-    // let downloader = Supervisor::start(|| DownloadActor);
+    // let downloader = Supervisor::start(move || DownloadActor::new(provider.clone()));
     // let processor = Supervisor::start(|| ProcessActor);
     // let _ = downloader.join(processor).await;
 
     // todo same story with the loop - if main isn't looping, actors won't have time to act
+    // every tick downloads the same from..to again and reruns each indicator over all of it. The streaming
+    // indicators could keep per-ticker state between ticks and only take the new bars, but not until
+    // the range moves forward with the clock - out of scope for now.
     let mut interval = stream::interval(Duration::from_secs(10));
+    let mut first_run = true;
+    // tickers we've already said are sitting out until their next session
+    let mut closed: HashSet<String> = HashSet::new();
+    let mut run_id = 0;
     while interval.next().await.is_some() {
-        for ticker in opts.tickers.split(",").collect::<Vec<&str>>() {
+        let mut due = vec![];
+        for info in &tickers {
+            let ticker = info.symbol.as_str();
+            // nothing new comes in while the market is shut, so only the first run fetches regardless.
+            // no calendar for the exchange = no idea when it's shut, so those always get polled
+            let now = Utc::now();
+            let shut = config
+                .calendar
+                .or_else(|| calendar::for_info(info))
+                .filter(|c| !first_run && !opts.always_poll && !c.is_open(now));
+            if let Some(calendar) = shut {
+                if closed.insert(ticker.to_string()) {
+                    match calendar.next_session(now) {
+                        Some(next) => println!(
+                            "{} closed, skipping {} until {}",
+                            calendar.name,
+                            ticker,
+                            next.open.to_rfc3339()
+                        ),
+                        None => println!("{} closed, skipping {}", calendar.name, ticker),
+                    }
+                }
+                continue;
+            }
+            closed.remove(ticker);
+            due.push(info);
+        }
+        run_id += 1;
+        let run = Run {
+            id: run_id,
+            tickers: due.len(),
+        };
+        for info in due {
             // prep msg
             let msg = DownloadMsg {
-                ticker: ticker.into(),
+                info: info.clone(),
                 from,
                 to,
+                config: config.clone(),
+                run,
             };
             // send it
             let _ = Broker::from_registry().await.unwrap().publish(msg);
         }
+        first_run = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(id: u64, tickers: usize, symbol: Option<&str>) -> CollectMsg {
+        CollectMsg {
+            run: Run { id, tickers },
+            series: symbol.map(|s| (s.to_string(), vec![], Tz::UTC)),
+        }
+    }
+
+    fn symbols(collected: Option<Vec<Collected>>) -> Option<Vec<String>> {
+        collected.map(|c| c.into_iter().map(|(s, _, _)| s).collect())
+    }
+
+    #[test]
+    fn collect_waits_for_every_ticker_of_the_run() {
+        let mut pending = HashMap::new();
+        assert_eq!(symbols(collect(&mut pending, msg(1, 3, Some("A")))), None);
+        // a later run finishing first doesn't get mixed up with this one
+        assert_eq!(symbols(collect(&mut pending, msg(2, 2, Some("X")))), None);
+        assert_eq!(symbols(collect(&mut pending, msg(1, 3, None))), None);
+        assert_eq!(
+            symbols(collect(&mut pending, msg(2, 2, Some("Y")))),
+            Some(vec!["X".to_string(), "Y".to_string()])
+        );
+        // the failed ticker counts towards the run, but has nothing to hand over
+        assert_eq!(
+            symbols(collect(&mut pending, msg(1, 3, Some("B")))),
+            Some(vec!["A".to_string(), "B".to_string()])
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn collect_a_run_where_everything_failed() {
+        let mut pending = HashMap::new();
+        assert_eq!(symbols(collect(&mut pending, msg(1, 2, None))), None);
+        assert_eq!(
+            symbols(collect(&mut pending, msg(1, 2, None))),
+            Some(vec![])
+        );
+        assert!(pending.is_empty());
     }
 }
@@ -0,0 +1,82 @@
+use std::fmt;
+
+use chrono_tz::Tz;
+use yahoo_finance_api::{YMetaData, YQuoteItemOpt};
+
+/// What we know about a symbol beyond its prices.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickerInfo {
+    pub symbol: String,
+    pub long_name: Option<String>,
+    /// the vendor's exchange code, eg NMS for nasdaq
+    pub exchange: Option<String>,
+    pub currency: Option<String>,
+    /// EQUITY, ETF, INDEX, CURRENCY, ...
+    pub instrument_type: Option<String>,
+    pub timezone: Option<Tz>,
+}
+
+impl TickerInfo {
+    /// for sources that only know prices
+    pub fn unknown(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_uppercase(),
+            ..Self::default()
+        }
+    }
+}
+
+impl fmt::Display for TickerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".into());
+        write!(
+            f,
+            "{} - {} ({}, {}, {}, {})",
+            self.symbol,
+            or_dash(&self.long_name),
+            or_dash(&self.exchange),
+            or_dash(&self.currency),
+            or_dash(&self.instrument_type),
+            self.timezone.map_or("-", |tz| tz.name()),
+        )
+    }
+}
+
+impl From<&YMetaData> for TickerInfo {
+    fn from(meta: &YMetaData) -> Self {
+        let non_empty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+        Self {
+            symbol: meta.symbol.clone(),
+            // the chart meta has no name - search fills it in
+            long_name: None,
+            exchange: non_empty(&meta.exchange_name),
+            currency: non_empty(&meta.currency),
+            instrument_type: non_empty(&meta.instrument_type),
+            timezone: meta.exchange_timezone_name.parse().ok(),
+        }
+    }
+}
+
+/// One hit from a symbol search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub symbol: String,
+    pub name: String,
+    pub exchange: String,
+    pub instrument_type: String,
+}
+
+impl From<&YQuoteItemOpt> for SearchResult {
+    fn from(item: &YQuoteItemOpt) -> Self {
+        Self {
+            symbol: item.symbol.clone(),
+            name: item
+                .long_name
+                .clone()
+                .or_else(|| item.short_name.clone())
+                .unwrap_or_default(),
+            exchange: item.exchange.clone(),
+            instrument_type: item.quote_type.clone(),
+        }
+    }
+}
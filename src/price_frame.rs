@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+
+use crate::cleaning::MissingPolicy;
+use crate::download_data::YQuote;
+use crate::interval::Interval;
+use crate::process_data::Data;
+use crate::streaming::PriceField;
+
+/// What two bars have to share to count as the same bar: the trading date for daily and longer bars,
+/// as exchanges in different zones stamp the same day at different instants, the instant itself intraday.
+pub fn bar_key(timestamp: DateTime<Utc>, timezone: Tz, interval: Interval) -> NaiveDateTime {
+    if interval.is_intraday() {
+        timestamp.naive_utc()
+    } else {
+        timestamp
+            .with_timezone(&timezone)
+            .date()
+            .naive_local()
+            .and_hms(0, 0, 0)
+    }
+}
+
+/// Which rows survive when tickers are lined up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Join {
+    /// only bars every ticker has
+    Inner,
+    /// every bar any ticker has, with holes where the others don't
+    #[default]
+    Outer,
+}
+
+/// Several tickers' bars on one shared index - a row per bar key (see `bar_key`), a column per ticker,
+/// None where a ticker has no bar for that row. The bars keep their own timestamps.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriceFrame {
+    interval: Interval,
+    index: Vec<NaiveDateTime>,
+    symbols: Vec<String>,
+    /// zone each symbol's bars are dated in
+    timezones: Vec<Tz>,
+    /// one per symbol, each as long as the index
+    columns: Vec<Vec<Option<YQuote>>>,
+}
+
+impl PriceFrame {
+    pub fn new(interval: Interval) -> Self {
+        Self {
+            interval,
+            ..Self::default()
+        }
+    }
+
+    /// Lines up every (symbol, bars, zone the bars are dated in) in one go.
+    pub fn join(
+        interval: Interval,
+        join: Join,
+        series: impl IntoIterator<Item = (String, Data, Tz)>,
+    ) -> Self {
+        let mut frame = Self::new(interval);
+        for (symbol, data, timezone) in series {
+            frame.insert(&symbol, &data, timezone);
+        }
+        match join {
+            Join::Inner => frame.complete_rows(),
+            Join::Outer => frame,
+        }
+    }
+
+    /// Adds (or replaces) a ticker, outer joined - rows only it has get holes in every other column.
+    /// Two bars landing on the same key keep the later one.
+    pub fn insert(&mut self, symbol: &str, data: &[YQuote], timezone: Tz) {
+        let mut keyed: BTreeMap<NaiveDateTime, YQuote> = data
+            .iter()
+            .map(|q| (bar_key(q.timestamp, timezone, self.interval), q.clone()))
+            .collect();
+        if let Some(i) = self.position(symbol) {
+            self.symbols.remove(i);
+            self.timezones.remove(i);
+            self.columns.remove(i);
+        }
+        let mut index: Vec<NaiveDateTime> = self.index.clone();
+        index.extend(keyed.keys().copied());
+        index.sort();
+        index.dedup();
+
+        let old: Vec<BTreeMap<NaiveDateTime, YQuote>> = self
+            .columns
+            .iter()
+            .map(|column| {
+                self.index
+                    .iter()
+                    .zip(column)
+                    .filter_map(|(key, q)| Some((*key, q.clone()?)))
+                    .collect()
+            })
+            .collect();
+        self.columns = old
+            .into_iter()
+            .map(|mut column| index.iter().map(|key| column.remove(key)).collect())
+            .collect();
+        self.columns
+            .push(index.iter().map(|key| keyed.remove(key)).collect());
+        self.symbols.push(symbol.to_string());
+        self.timezones.push(timezone);
+        self.index = index;
+    }
+
+    pub fn interval(&self) -> Interval {
+        self.interval
+    }
+
+    pub fn index(&self) -> &[NaiveDateTime] {
+        &self.index
+    }
+
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// zone a ticker's bars are dated in
+    pub fn timezone(&self, symbol: &str) -> Option<Tz> {
+        Some(self.timezones[self.position(symbol)?])
+    }
+
+    fn position(&self, symbol: &str) -> Option<usize> {
+        self.symbols
+            .iter()
+            .position(|s| s.eq_ignore_ascii_case(symbol))
+    }
+
+    /// one ticker's bars, row for row with the index
+    pub fn column(&self, symbol: &str) -> Option<&[Option<YQuote>]> {
+        Some(&self.columns[self.position(symbol)?])
+    }
+
+    /// one field of one ticker, row for row with the index
+    pub fn field(&self, symbol: &str, field: PriceField) -> Option<Vec<Option<Decimal>>> {
+        Some(
+            self.column(symbol)?
+                .iter()
+                .map(|q| q.as_ref().map(|q| field.of(q)))
+                .collect(),
+        )
+    }
+
+    /// (key, a, b) for the rows where both tickers have a bar - what pairwise numbers run on
+    pub fn pair(
+        &self,
+        a: &str,
+        b: &str,
+        field: PriceField,
+    ) -> Option<Vec<(NaiveDateTime, Decimal, Decimal)>> {
+        let (a, b) = (self.field(a, field)?, self.field(b, field)?);
+        Some(
+            self.index
+                .iter()
+                .zip(a.into_iter().zip(b))
+                .filter_map(|(key, (a, b))| Some((*key, a?, b?)))
+                .collect(),
+        )
+    }
+
+    /// A ticker's bars back as plain data, holes left out.
+    pub fn to_data(&self, symbol: &str) -> Option<Data> {
+        Some(self.column(symbol)?.iter().flatten().cloned().collect())
+    }
+
+    fn keep_rows(&self, keep: impl Fn(usize) -> bool) -> Self {
+        let rows: Vec<usize> = (0..self.len()).filter(|i| keep(*i)).collect();
+        Self {
+            interval: self.interval,
+            index: rows.iter().map(|i| self.index[*i]).collect(),
+            symbols: self.symbols.clone(),
+            timezones: self.timezones.clone(),
+            columns: self
+                .columns
+                .iter()
+                .map(|column| rows.iter().map(|i| column[*i].clone()).collect())
+                .collect(),
+        }
+    }
+
+    /// only the rows every ticker has a bar for - an inner join after the fact
+    pub fn complete_rows(&self) -> Self {
+        self.keep_rows(|i| self.columns.iter().all(|column| column[i].is_some()))
+    }
+
+    /// only the rows keyed within from..=to
+    pub fn between(&self, from: NaiveDateTime, to: NaiveDateTime) -> Self {
+        self.keep_rows(|i| self.index[i] >= from && self.index[i] <= to)
+    }
+
+    /// Fills the holes the way cleaning fills bad bars: ForwardFill carries the last bar's close flat with
+    /// no volume, Interpolate draws a line to the next bar (and carries forward past the last one), Drop
+    /// keeps only complete rows. Holes before a ticker's first bar stay - there's nothing to fill from.
+    /// Filled bars are dated on their row in the ticker's own zone (see `timestamp_at`).
+    pub fn fill(&self, policy: MissingPolicy) -> Self {
+        if policy == MissingPolicy::Drop {
+            return self.complete_rows();
+        }
+        let mut filled = self.clone();
+        for (c, column) in self.columns.iter().enumerate() {
+            for (i, q) in column.iter().enumerate() {
+                if q.is_some() {
+                    continue;
+                }
+                let prev = filled.columns[c][..i].iter().rev().flatten().next();
+                let next = column[i + 1..].iter().flatten().next();
+                let timestamp = match prev.or(next) {
+                    Some(like) => self.timestamp_at(i, self.timezones[c], like),
+                    None => continue,
+                };
+                let bar = match (policy, prev, next) {
+                    (MissingPolicy::Interpolate, Some(p), Some(n)) => {
+                        let w = Decimal::from((timestamp - p.timestamp).num_seconds())
+                            / Decimal::from((n.timestamp - p.timestamp).num_seconds().max(1));
+                        let lerp = |a: Decimal, b: Decimal| a + (b - a) * w;
+                        YQuote {
+                            timestamp,
+                            open: lerp(p.open, n.open),
+                            high: lerp(p.high, n.high),
+                            low: lerp(p.low, n.low),
+                            volume: 0,
+                            close: lerp(p.close, n.close),
+                            adjclose: lerp(p.adjclose, n.adjclose),
+                        }
+                    }
+                    (_, Some(p), _) => YQuote {
+                        timestamp,
+                        open: p.close,
+                        high: p.close,
+                        low: p.close,
+                        volume: 0,
+                        close: p.close,
+                        adjclose: p.adjclose,
+                    },
+                    _ => continue,
+                };
+                filled.columns[c][i] = Some(bar);
+            }
+        }
+        filled
+    }
+
+    /// When a bar of `like`'s ticker (dated in `timezone`) on row i would be - so it keys back onto the
+    /// same row. Daily bars get `like`'s time of day on the row's date in their own zone; other
+    /// tickers' bars on the row can be on a different date there.
+    fn timestamp_at(&self, i: usize, timezone: Tz, like: &YQuote) -> DateTime<Utc> {
+        let key = self.index[i];
+        if self.interval.is_intraday() {
+            return Utc.from_utc_datetime(&key);
+        }
+        let time = like.timestamp.with_timezone(&timezone).time();
+        timezone
+            .from_local_datetime(&key.date().and_time(time))
+            .earliest()
+            // that time doesn't exist on the day (dst) - midnight always does
+            .or_else(|| timezone.from_local_datetime(&key).earliest())
+            .map_or_else(|| Utc.from_utc_datetime(&key), |t| t.with_timezone(&Utc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn quote(timestamp: DateTime<Utc>, price: i64) -> YQuote {
+        let price = Decimal::from(price);
+        YQuote {
+            timestamp,
+            open: price,
+            high: price,
+            low: price,
+            volume: 1000,
+            close: price,
+            adjclose: price,
+        }
+    }
+
+    fn day(d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2021, 1, d).and_hms(0, 0, 0)
+    }
+
+    /// closing at 16:00 in new york, the 4th to the 7th
+    fn new_york() -> Data {
+        (4..=7)
+            .map(|d| quote(Utc.ymd(2021, 1, d).and_hms(21, 0, 0), 6 + d as i64))
+            .collect()
+    }
+
+    /// 08:30 in tokyo on the 5th, 7th and 8th - the day before in utc
+    fn tokyo() -> Data {
+        vec![
+            quote(Utc.ymd(2021, 1, 4).and_hms(23, 30, 0), 100),
+            quote(Utc.ymd(2021, 1, 6).and_hms(23, 30, 0), 120),
+            quote(Utc.ymd(2021, 1, 7).and_hms(23, 30, 0), 130),
+        ]
+    }
+
+    fn frame(join: Join) -> PriceFrame {
+        PriceFrame::join(
+            Interval::OneDay,
+            join,
+            vec![
+                ("SPY".to_string(), new_york(), Tz::America__New_York),
+                ("TOKYO".to_string(), tokyo(), Tz::Asia__Tokyo),
+            ],
+        )
+    }
+
+    fn closes(frame: &PriceFrame, symbol: &str) -> Vec<Option<Decimal>> {
+        frame.field(symbol, PriceField::Close).unwrap()
+    }
+
+    fn some(prices: &[Option<i64>]) -> Vec<Option<Decimal>> {
+        prices.iter().map(|p| p.map(Decimal::from)).collect()
+    }
+
+    #[test]
+    fn bars_line_up_on_their_own_trading_date() {
+        let outer = frame(Join::Outer);
+        assert_eq!(outer.index(), &[day(4), day(5), day(6), day(7), day(8)]);
+        assert_eq!(outer.symbols(), &["SPY", "TOKYO"]);
+        assert_eq!(
+            closes(&outer, "SPY"),
+            some(&[Some(10), Some(11), Some(12), Some(13), None])
+        );
+        // 23:30 utc on the 4th is the 5th in tokyo, and lines up with new york's 5th
+        assert_eq!(
+            closes(&outer, "tokyo"),
+            some(&[None, Some(100), None, Some(120), Some(130)])
+        );
+        assert_eq!(outer.timezone("TOKYO"), Some(Tz::Asia__Tokyo));
+        let pair = outer.pair("SPY", "TOKYO", PriceField::Close).unwrap();
+        let keys: Vec<NaiveDateTime> = pair.iter().map(|(k, _, _)| *k).collect();
+        assert_eq!(keys, vec![day(5), day(7)]);
+
+        let inner = frame(Join::Inner);
+        assert_eq!(inner.index(), &[day(5), day(7)]);
+        assert_eq!(closes(&inner, "SPY"), some(&[Some(11), Some(13)]));
+        assert_eq!(closes(&inner, "TOKYO"), some(&[Some(100), Some(120)]));
+        assert_eq!(inner, outer.complete_rows());
+    }
+
+    #[test]
+    fn intraday_bars_line_up_on_the_instant() {
+        let frame = PriceFrame::join(
+            Interval::OneHour,
+            Join::Outer,
+            vec![
+                ("SPY".to_string(), new_york(), Tz::America__New_York),
+                ("TOKYO".to_string(), tokyo(), Tz::Asia__Tokyo),
+            ],
+        );
+        assert_eq!(frame.len(), 7);
+        assert!(frame
+            .pair("SPY", "TOKYO", PriceField::Close)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn insert_replaces_a_ticker() {
+        let mut frame = frame(Join::Outer);
+        frame.insert("tokyo", &tokyo()[..1], Tz::Asia__Tokyo);
+        assert_eq!(frame.symbols(), &["SPY", "tokyo"]);
+        // the 8th was only ever tokyo's, but the index keeps the rows it had
+        assert_eq!(frame.len(), 5);
+        assert_eq!(
+            closes(&frame, "TOKYO"),
+            some(&[None, Some(100), None, None, None])
+        );
+        assert_eq!(
+            closes(&frame, "SPY")[..4],
+            some(&[Some(10), Some(11), Some(12), Some(13)])[..]
+        );
+    }
+
+    #[test]
+    fn to_data_gives_back_what_went_in() {
+        let frame = frame(Join::Outer);
+        assert_eq!(frame.to_data("SPY"), Some(new_york()));
+        assert_eq!(frame.to_data("TOKYO"), Some(tokyo()));
+        assert_eq!(frame.to_data("NOPE"), None);
+        let inner = self::frame(Join::Inner);
+        assert_eq!(inner.to_data("TOKYO"), Some(tokyo()[..2].to_vec()));
+    }
+
+    #[test]
+    fn between_is_inclusive_of_both_ends() {
+        let frame = frame(Join::Outer).between(day(5), day(7));
+        assert_eq!(frame.index(), &[day(5), day(6), day(7)]);
+        assert_eq!(closes(&frame, "SPY"), some(&[Some(11), Some(12), Some(13)]));
+        assert_eq!(closes(&frame, "TOKYO"), some(&[Some(100), None, Some(120)]));
+        assert!(self::frame(Join::Outer).between(day(9), day(10)).is_empty());
+    }
+
+    #[test]
+    fn forward_fill_carries_the_close_flat() {
+        let filled = frame(Join::Outer).fill(MissingPolicy::ForwardFill);
+        assert_eq!(
+            closes(&filled, "SPY"),
+            some(&[Some(10), Some(11), Some(12), Some(13), Some(13)])
+        );
+        // nothing before tokyo's first bar to fill the 4th from
+        assert_eq!(
+            closes(&filled, "TOKYO"),
+            some(&[None, Some(100), Some(100), Some(120), Some(130)])
+        );
+        let tokyo = filled.column("TOKYO").unwrap()[2].clone().unwrap();
+        assert_eq!(
+            tokyo,
+            YQuote {
+                volume: 0,
+                ..quote(Utc.ymd(2021, 1, 5).and_hms(23, 30, 0), 100)
+            }
+        );
+        let spy = filled.column("SPY").unwrap()[4].clone().unwrap();
+        assert_eq!(
+            spy,
+            YQuote {
+                volume: 0,
+                ..quote(Utc.ymd(2021, 1, 8).and_hms(21, 0, 0), 13)
+            }
+        );
+        // filled bars key back onto the rows they fill
+        assert_eq!(
+            bar_key(tokyo.timestamp, Tz::Asia__Tokyo, Interval::OneDay),
+            day(6)
+        );
+        assert_eq!(
+            bar_key(spy.timestamp, Tz::America__New_York, Interval::OneDay),
+            day(8)
+        );
+    }
+
+    #[test]
+    fn interpolate_draws_a_line_between_bars() {
+        let filled = frame(Join::Outer).fill(MissingPolicy::Interpolate);
+        // halfway in time between 100 and 120
+        assert_eq!(
+            closes(&filled, "TOKYO"),
+            some(&[None, Some(100), Some(110), Some(120), Some(130)])
+        );
+        let tokyo = filled.column("TOKYO").unwrap()[2].clone().unwrap();
+        assert_eq!(tokyo.timestamp, Utc.ymd(2021, 1, 5).and_hms(23, 30, 0));
+        assert_eq!(tokyo.volume, 0);
+        // nothing after new york's last bar, so it carries forward
+        assert_eq!(closes(&filled, "SPY")[4], Some(Decimal::from(13)));
+    }
+
+    #[test]
+    fn drop_keeps_complete_rows() {
+        let frame = frame(Join::Outer);
+        assert_eq!(frame.fill(MissingPolicy::Drop), frame.complete_rows());
+        assert_eq!(frame.fill(MissingPolicy::Drop).index(), &[day(5), day(7)]);
+    }
+}
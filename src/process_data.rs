@@ -1,37 +1,99 @@
-use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
 
-use crate::download_data::YQuote;
-use chrono::{TimeZone, Utc};
+use crate::adjustment::{adjust, validate_against_vendor, Adjustment};
+use crate::benchmark::{Benchmark, BenchmarkStats};
+use crate::calendar::{self, find_gaps, TradingCalendar};
+use crate::cleaning::{clean, CleaningPolicy};
+use crate::corporate_actions::CorporateActions;
+use crate::download_data::{History, YQuote};
+use crate::drawdown::DrawdownStats;
+use crate::indicators::IndicatorColumn;
+use crate::interval::Interval;
+use crate::metadata::TickerInfo;
+use crate::risk::{RiskConfig, RiskStats};
+use crate::streaming::{run, RollingMinMax, RollingSma};
+use crate::timezone::OutputZone;
+use chrono_tz::Tz;
 use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
 pub type Data = Vec<YQuote>;
 
+/// Everything about how a ticker's data gets processed, bar the data itself.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessConfig {
+    pub interval: Interval,
+    pub adjustment: Adjustment,
+    /// the source's bars already have splits in them (see `QuoteProvider::split_adjusted`)
+    pub split_adjusted: bool,
+    /// print how far our own adjustment is from the vendor's adjclose, flagging bars further apart
+    /// than this fraction. None = don't
+    pub validate_adjustment: Option<Decimal>,
+    /// None = use the data as downloaded
+    pub cleaning: Option<CleaningPolicy>,
+    /// None = go by the ticker's exchange, or failing that its suffix
+    pub calendar: Option<&'static TradingCalendar>,
+    /// zone intraday bar times are printed in
+    pub output_zone: OutputZone,
+    /// extra output columns, each showing the indicator's latest value
+    pub indicators: Vec<IndicatorColumn>,
+    /// also write every bar's indicator values to <dir>/<TICKER>.csv
+    pub series_dir: Option<PathBuf>,
+    /// None = leave the risk columns out
+    pub risk: Option<RiskConfig>,
+    /// how many of the deepest drawdowns to list. None = leave the drawdown columns out
+    pub drawdowns: Option<usize>,
+    /// what every ticker gets compared against. None = no benchmark columns
+    pub benchmark: Option<Arc<Benchmark>>,
+}
+
 pub struct ProcessedData {
     pub min_: Decimal,
     pub max_: Decimal,
     pub smas: Vec<Decimal>,
     pub abs_diff: Decimal,
     pub percent_diff: Decimal,
+    /// cash paid per share over the period
+    pub dividends: Decimal,
+    /// (column, values) - None where the series was too short for the indicator
+    pub indicators: Vec<(String, Option<Vec<Decimal>>)>,
+    /// None unless the config asked for them
+    pub risk: Option<RiskStats>,
+    pub drawdowns: Option<DrawdownStats>,
+    pub benchmark: Option<BenchmarkStats>,
+    /// the bars as processed, adjclose being the adjusted close everything above ran on
+    pub data: Data,
+    /// zone the bars are dated in
+    pub timezone: Tz,
 }
 
-pub fn extract_adjclose(quotes: &[YQuote]) -> Vec<Decimal> {
-    quotes.iter().map(|q| q.adjclose).collect()
+/// Adjusted close series - either the vendor's adjclose or one we rebuild from close + corporate actions.
+pub fn extract_adjclose(
+    quotes: &[YQuote],
+    actions: &CorporateActions,
+    adjustment: Adjustment,
+    split_adjusted: bool,
+) -> Vec<Decimal> {
+    match adjustment {
+        Adjustment::Vendor => quotes.iter().map(|q| q.adjclose).collect(),
+        Adjustment::Raw => adjust(quotes, actions, adjustment, split_adjusted)
+            .iter()
+            .map(|q| q.close)
+            .collect(),
+        _ => adjust(quotes, actions, adjustment, split_adjusted)
+            .iter()
+            .map(|q| q.adjclose)
+            .collect(),
+    }
 }
 
+/// Still O(n) for a one-off pass, but built on RollingMinMax, which takes each new bar in O(1).
 pub fn min_and_max(series: &[Decimal]) -> (Decimal, Decimal) {
-    // todo still think this can be done better than O(n) - will see if it's a bottleneck
-    let mut min_ = series[0];
-    let mut max_ = series[0];
-    for price in series {
-        if *price < min_ {
-            min_ = *price
-        } else if *price > max_ {
-            max_ = *price
-        }
-    }
-    (min_, max_)
+    run(RollingMinMax::expanding(), series)
+        .pop()
+        .unwrap_or((series[0], series[0]))
 }
 
 /// only calculates when enough days
@@ -39,11 +101,7 @@ pub fn n_window_sma(n: usize, series: &[Decimal]) -> Option<Vec<Decimal>> {
     if n > series.len() {
         return None;
     }
-    let smas: Vec<Decimal> = series
-        .windows(n)
-        .map(|w| w.iter().sum::<Decimal>() / Decimal::from(n))
-        .collect();
-    Some(smas)
+    Some(run(RollingSma::new(n), series))
 }
 
 pub fn price_diff(series: &[Decimal]) -> (Decimal, Decimal) {
@@ -56,41 +114,219 @@ pub fn price_diff(series: &[Decimal]) -> (Decimal, Decimal) {
     (last - first, last / first - Decimal::from(1))
 }
 
-pub fn process_data(quotes: Vec<YQuote>, ticker: String) -> ProcessedData {
+/// One row per bar: its time, the bar itself and every indicator column, blank until the indicator
+/// has warmed up. Indicator values line up with the end of the bars.
+pub fn write_series(
+    path: &Path,
+    quotes: &[YQuote],
+    indicators: &[(String, Option<Vec<Decimal>>)],
+    timezone: Tz,
+    config: &ProcessConfig,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    let mut header: Vec<String> = ["time", "open", "high", "low", "close", "adjclose", "volume"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect();
+    header.extend(indicators.iter().map(|(name, _)| name.clone()));
+    wtr.write_record(&header)?;
+    for (i, q) in quotes.iter().enumerate() {
+        let mut record = vec![
+            config
+                .output_zone
+                .format_bar(q.timestamp, timezone, config.interval),
+            q.open.to_string(),
+            q.high.to_string(),
+            q.low.to_string(),
+            q.close.to_string(),
+            q.adjclose.to_string(),
+            q.volume.to_string(),
+        ];
+        record.extend(indicators.iter().map(|(_, values)| {
+            let values = values.as_deref().unwrap_or_default();
+            // bar i's value, counting back from the end
+            (i + values.len())
+                .checked_sub(quotes.len())
+                .map_or(String::new(), |j| values[j].to_string())
+        }));
+        wtr.write_record(&record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Prints the ticker's row (and whatever else the config asks for). Data there's nothing to be done
+/// with is reported here and comes back as None.
+pub fn process_data(
+    history: History,
+    info: &TickerInfo,
+    config: &ProcessConfig,
+) -> Option<ProcessedData> {
+    let ticker = info.symbol.clone();
     println!("START processing...");
 
     std::thread::sleep(Duration::from_secs(5));
     // tokio::time::sleep(tokio::time::Duration::from_secs(5)); // <-- won't work inside a normal (non async) fn
 
+    // None = an exchange we've no calendar for, so no gap check
+    let calendar = config.calendar.or_else(|| calendar::for_info(info));
+    // providers that don't know the exchange's zone get the calendar's
+    let timezone = history
+        .timezone
+        .or(info.timezone)
+        .or(calendar.map(|c| c.timezone))
+        .unwrap_or(Tz::UTC);
+    let (quotes, actions) = (history.quotes, &history.actions);
+    if let Some(calendar) = calendar {
+        let gaps = find_gaps(calendar, &quotes, timezone, config.interval);
+        if !gaps.is_empty() {
+            let missing: usize = gaps.iter().map(|g| g.sessions).sum();
+            let gaps: Vec<String> = gaps.iter().map(|g| g.to_string()).collect();
+            println!(
+                "{}: no bars for {} {} sessions: {}",
+                ticker,
+                missing,
+                calendar.name,
+                gaps.join(", ")
+            );
+        }
+    }
+
+    let quotes = match &config.cleaning {
+        Some(policy) => {
+            let (cleaned, report) = clean(&quotes, &ticker, policy);
+            if !report.is_clean() {
+                println!("{}", report);
+            }
+            cleaned
+        }
+        None => quotes,
+    };
+    if quotes.is_empty() {
+        println!("{}: no bars to process", ticker);
+        return None;
+    }
+
     let ts = quotes[0].timestamp;
     let close = quotes[0].close;
 
-    let mut adjclose_series = extract_adjclose(&quotes);
+    let adjclose_series =
+        extract_adjclose(&quotes, actions, config.adjustment, config.split_adjusted);
     let (min_, max_) = min_and_max(&adjclose_series);
-    let smas = n_window_sma(30, &adjclose_series).unwrap();
+    // "30d avg" means 30 calendar days, so the window in bars depends on the interval
+    // empty when there's less than that
+    let smas = n_window_sma(
+        config.interval.bars_in(chrono::Duration::days(30)),
+        &adjclose_series,
+    )
+    .unwrap_or_default();
     let (abs_diff, percent_diff) = price_diff(&adjclose_series);
+    let risk = config
+        .risk
+        .map(|risk| RiskStats::compute(&adjclose_series, config.interval, &risk));
+    let dividends = actions.total_dividends();
+    let drawdowns = config.drawdowns.map(|top| {
+        let timestamps: Vec<_> = quotes.iter().map(|q| q.timestamp).collect();
+        DrawdownStats::compute(&timestamps, &adjclose_series, config.interval, top)
+    });
+    let adjusted: Data = quotes
+        .iter()
+        .zip(&adjclose_series)
+        .map(|(q, adjclose)| YQuote {
+            adjclose: *adjclose,
+            ..q.clone()
+        })
+        .collect();
+    let benchmark = config
+        .benchmark
+        .as_ref()
+        .map(|b| BenchmarkStats::compute(&adjusted, timezone, b, config.interval));
+    let indicators: Vec<(String, Option<Vec<Decimal>>)> = config
+        .indicators
+        .iter()
+        .flat_map(|i| {
+            i.columns()
+                .into_iter()
+                .zip(i.compute(&quotes, &adjclose_series))
+        })
+        .collect();
+
+    if let Some(dir) = &config.series_dir {
+        let path = dir.join(format!("{}.csv", ticker));
+        match write_series(&path, &quotes, &indicators, timezone, config) {
+            Ok(()) => println!("{}: wrote series to {}", ticker, path.display()),
+            Err(e) => println!(
+                "{}: FAILED writing series to {}: {}",
+                ticker,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    if let Some(tolerance) = config.validate_adjustment {
+        let ours = adjust(&quotes, actions, config.adjustment, config.split_adjusted);
+        let report = validate_against_vendor(&quotes, &ours, tolerance);
+        println!(
+            "{} {} adjustment vs vendor adjclose: {}",
+            ticker, config.adjustment, report
+        );
+    }
 
     println!("END processing...");
 
     // write output
     let mut wtr = csv::Writer::from_writer(io::stdout());
-    wtr.write_record(&[
-        Utc.timestamp(ts as i64, 0).to_rfc3339(),
-        ticker.into(),
+    let mut record = vec![
+        config.output_zone.format_bar(ts, timezone, config.interval),
+        ticker,
+        info.currency.clone().unwrap_or_default(),
+        info.exchange.clone().unwrap_or_default(),
         close.round_dp(2).to_string(),
         (percent_diff * Decimal::from(100)).round_dp(2).to_string(),
         min_.round_dp(2).to_string(),
         max_.round_dp(2).to_string(),
-        smas[smas.len() - 1].round_dp(2).to_string(),
-    ])
-    .unwrap();
+        smas.last()
+            .map_or(String::new(), |sma| sma.round_dp(2).to_string()),
+    ];
+    // blank when there weren't enough bars for the indicator
+    record.extend(indicators.iter().map(|(_, values)| {
+        values
+            .as_ref()
+            .and_then(|v| v.last())
+            .map_or(String::new(), |v| v.round_dp(2).to_string())
+    }));
+    if let Some(risk) = &risk {
+        record.extend(risk.row());
+    }
+    let fmt = |ts| config.output_zone.format_bar(ts, timezone, config.interval);
+    if let Some(drawdowns) = &drawdowns {
+        record.extend(drawdowns.row(fmt));
+    }
+    if let Some(benchmark) = &benchmark {
+        record.extend(benchmark.row());
+    }
+    wtr.write_record(&record).unwrap();
     wtr.flush().unwrap();
+    if let Some(drawdowns) = drawdowns.as_ref().filter(|d| !d.episodes.is_empty()) {
+        println!("{} deepest drawdowns:", info.symbol);
+        for line in drawdowns.table(fmt) {
+            println!("{}", line);
+        }
+    }
 
-    ProcessedData {
+    Some(ProcessedData {
         min_,
         max_,
         smas,
         abs_diff,
         percent_diff,
-    }
+        dividends,
+        indicators,
+        risk,
+        drawdowns,
+        benchmark,
+        data: adjusted,
+        timezone,
+    })
 }
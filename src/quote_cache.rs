@@ -0,0 +1,808 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rust_decimal::Decimal;
+
+use crate::corporate_actions::{CorporateActions, Dividend, Split};
+use crate::download_data::{FetchError, History, QuoteProvider, YQuote};
+use crate::interval::Interval;
+use crate::metadata::{SearchResult, TickerInfo};
+use crate::process_data::Data;
+use crate::rate_limit::Budget;
+
+/// Bars younger than this can still change (today's daily bar moves all day), so we never treat them as cached.
+const SETTLE_SECS: u64 = 24 * 60 * 60;
+
+/// Summary of what's cached for one ticker + interval.
+#[derive(Clone, Debug)]
+pub struct CacheEntry {
+    pub ticker: String,
+    pub interval: Interval,
+    pub bars: usize,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    /// ranges (epoch secs, inclusive) that we've already asked the provider for
+    pub covered: Vec<(u64, u64)>,
+    pub dividends: usize,
+    pub splits: usize,
+}
+
+impl CacheEntry {
+    /// None matches anything
+    pub fn matches(&self, ticker: Option<&str>, interval: Option<Interval>) -> bool {
+        ticker.is_none_or(|t| t.eq_ignore_ascii_case(&self.ticker))
+            && interval.is_none_or(|i| i == self.interval)
+    }
+}
+
+/// On-disk quote store, one pair of files per ticker + interval:
+/// - `<TICKER>_<interval>.csv` - append-only bars, a later row for the same timestamp wins
+/// - `<TICKER>_<interval>.ranges` - which from..to ranges have already been fetched, so holidays don't look like gaps
+///
+/// plus `<TICKER>.actions` with the dividends and splits and `<TICKER>.meta` with the exchange timezone,
+/// neither of which depend on the interval. Times are stored as epoch seconds.
+pub struct QuoteCache {
+    dir: PathBuf,
+}
+
+impl QuoteCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, ticker: &str, interval: Interval, ext: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}_{}.{}", ticker.to_uppercase(), interval, ext))
+    }
+
+    /// every cached bar for the ticker, sorted and de-duplicated by timestamp
+    pub fn load(&self, ticker: &str, interval: Interval) -> io::Result<Data> {
+        let file = match File::open(self.path(ticker, interval, "csv")) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        // BTreeMap keeps them sorted and lets later rows overwrite earlier ones
+        let mut bars = BTreeMap::new();
+        for line in BufReader::new(file).lines() {
+            if let Some(q) = parse_row(&line?) {
+                bars.insert(q.timestamp, q);
+            }
+        }
+        Ok(bars.into_values().collect())
+    }
+
+    /// appends the bars that are new or changed - re-fetching an unchanged range doesn't grow the file
+    pub fn append(&self, ticker: &str, interval: Interval, quotes: &[YQuote]) -> io::Result<()> {
+        let cached = self.load(ticker, interval)?;
+        let changed: Vec<&YQuote> = quotes
+            .iter()
+            .filter(|q| {
+                cached
+                    .binary_search_by_key(&q.timestamp, |c| c.timestamp)
+                    .map_or(true, |i| cached[i] != **q)
+            })
+            .collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(ticker, interval, "csv"))?;
+        for q in changed {
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{}",
+                q.timestamp.timestamp(),
+                q.open,
+                q.high,
+                q.low,
+                q.volume,
+                q.close,
+                q.adjclose
+            )?;
+        }
+        Ok(())
+    }
+
+    fn actions_path(&self, ticker: &str) -> PathBuf {
+        self.dir.join(format!("{}.actions", ticker.to_uppercase()))
+    }
+
+    pub fn load_actions(&self, ticker: &str) -> io::Result<CorporateActions> {
+        let raw = match fs::read_to_string(self.actions_path(ticker)) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+            Err(e) => return Err(e),
+        };
+        let mut actions = CorporateActions::default();
+        for line in raw.lines() {
+            let cols: Vec<&str> = line.split(',').collect();
+            match cols.as_slice() {
+                ["dividend", ts, amount] => {
+                    if let (Ok(ts), Ok(amount)) = (ts.parse(), Decimal::from_str(amount)) {
+                        actions.dividends.push(Dividend {
+                            ex_date: to_utc(ts),
+                            amount,
+                        });
+                    }
+                }
+                ["split", ts, num, den] => {
+                    if let (Ok(ts), Ok(numerator), Ok(denominator)) =
+                        (ts.parse(), num.parse(), den.parse())
+                    {
+                        actions.splits.push(Split {
+                            date: to_utc(ts),
+                            numerator,
+                            denominator,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(actions)
+    }
+
+    /// merges `actions` into what's stored for the ticker
+    pub fn store_actions(&self, ticker: &str, actions: &CorporateActions) -> io::Result<()> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+        let mut all = self.load_actions(ticker)?;
+        all.merge(actions.clone());
+        let mut out = String::new();
+        for d in &all.dividends {
+            out.push_str(&format!(
+                "dividend,{},{}\n",
+                d.ex_date.timestamp(),
+                d.amount
+            ));
+        }
+        for s in &all.splits {
+            out.push_str(&format!(
+                "split,{},{},{}\n",
+                s.date.timestamp(),
+                s.numerator,
+                s.denominator
+            ));
+        }
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.actions_path(ticker), out)
+    }
+
+    fn meta_path(&self, ticker: &str) -> PathBuf {
+        self.dir.join(format!("{}.meta", ticker.to_uppercase()))
+    }
+
+    /// key,value lines - the value is everything after the first comma
+    fn load_meta(&self, ticker: &str) -> io::Result<BTreeMap<String, String>> {
+        let raw = match fs::read_to_string(self.meta_path(ticker)) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(raw
+            .lines()
+            .filter_map(|l| l.split_once(','))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect())
+    }
+
+    /// merges `updates` into what's stored for the ticker
+    fn store_meta(&self, ticker: &str, updates: &[(&str, String)]) -> io::Result<()> {
+        let mut meta = self.load_meta(ticker)?;
+        for (k, v) in updates {
+            meta.insert(k.to_string(), v.replace('\n', " "));
+        }
+        let out: String = meta.iter().map(|(k, v)| format!("{},{}\n", k, v)).collect();
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.meta_path(ticker), out)
+    }
+
+    pub fn load_timezone(&self, ticker: &str) -> io::Result<Option<Tz>> {
+        Ok(self
+            .load_meta(ticker)?
+            .get("timezone")
+            .and_then(|tz| tz.parse().ok()))
+    }
+
+    pub fn store_timezone(&self, ticker: &str, timezone: Tz) -> io::Result<()> {
+        self.store_meta(ticker, &[("timezone", timezone.name().to_string())])
+    }
+
+    /// None until `store_info` has been called for the ticker - a timezone on its own doesn't count
+    pub fn load_info(&self, ticker: &str) -> io::Result<Option<TickerInfo>> {
+        let meta = self.load_meta(ticker)?;
+        let symbol = match meta.get("symbol") {
+            Some(symbol) => symbol.clone(),
+            None => return Ok(None),
+        };
+        Ok(Some(TickerInfo {
+            symbol,
+            long_name: meta.get("long_name").cloned(),
+            exchange: meta.get("exchange").cloned(),
+            currency: meta.get("currency").cloned(),
+            instrument_type: meta.get("instrument_type").cloned(),
+            timezone: meta.get("timezone").and_then(|tz| tz.parse().ok()),
+        }))
+    }
+
+    pub fn store_info(&self, ticker: &str, info: &TickerInfo) -> io::Result<()> {
+        let mut updates = vec![("symbol", info.symbol.clone())];
+        let optional = [
+            ("long_name", &info.long_name),
+            ("exchange", &info.exchange),
+            ("currency", &info.currency),
+            ("instrument_type", &info.instrument_type),
+        ];
+        for (key, value) in optional.iter() {
+            if let Some(value) = value {
+                updates.push((key, value.clone()));
+            }
+        }
+        if let Some(tz) = info.timezone {
+            updates.push(("timezone", tz.name().to_string()));
+        }
+        self.store_meta(ticker, &updates)
+    }
+
+    pub fn covered(&self, ticker: &str, interval: Interval) -> io::Result<Vec<(u64, u64)>> {
+        let raw = match fs::read_to_string(self.path(ticker, interval, "ranges")) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        Ok(raw
+            .lines()
+            .filter_map(|l| {
+                let (from, to) = l.split_once(',')?;
+                Some((from.parse().ok()?, to.parse().ok()?))
+            })
+            .collect())
+    }
+
+    /// records from..to as fetched, merging it with whatever was already covered
+    pub fn mark_covered(
+        &self,
+        ticker: &str,
+        interval: Interval,
+        from: u64,
+        to: u64,
+    ) -> io::Result<()> {
+        if from > to {
+            return Ok(());
+        }
+        let mut ranges = self.covered(ticker, interval)?;
+        ranges.push((from, to));
+        ranges.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = vec![];
+        for (from, to) in ranges {
+            match merged.last_mut() {
+                Some(last) if from <= last.1 + 1 => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        let out: String = merged
+            .iter()
+            .map(|(from, to)| format!("{},{}\n", from, to))
+            .collect();
+        fs::write(self.path(ticker, interval, "ranges"), out)
+    }
+
+    /// The parts of from..to that haven't been fetched yet.
+    pub fn missing_ranges(
+        &self,
+        ticker: &str,
+        interval: Interval,
+        from: u64,
+        to: u64,
+    ) -> io::Result<Vec<(u64, u64)>> {
+        let mut missing = vec![];
+        let mut cursor = from;
+        for (c_from, c_to) in self.covered(ticker, interval)? {
+            if c_to < cursor || c_from > to {
+                continue;
+            }
+            if c_from > cursor {
+                missing.push((cursor, c_from - 1));
+            }
+            cursor = c_to.saturating_add(1);
+            if cursor > to {
+                break;
+            }
+        }
+        if cursor <= to {
+            missing.push((cursor, to));
+        }
+        Ok(missing)
+    }
+
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        // a range with no bars in it only leaves a .ranges file behind, so look at both
+        let mut keys = BTreeMap::new();
+        for file in dir {
+            let path = file?.path();
+            if !path
+                .extension()
+                .is_some_and(|ext| ext == "csv" || ext == "ranges")
+            {
+                continue;
+            }
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            if let Some((ticker, interval)) = stem.rsplit_once('_') {
+                if let Ok(interval) = Interval::from_str(interval) {
+                    keys.insert((ticker.to_string(), interval.as_str()), interval);
+                }
+            }
+        }
+        let mut entries = vec![];
+        for ((ticker, _), interval) in keys {
+            let bars = self.load(&ticker, interval)?;
+            let actions = self.load_actions(&ticker)?;
+            entries.push(CacheEntry {
+                dividends: actions.dividends.len(),
+                splits: actions.splits.len(),
+                bars: bars.len(),
+                first: bars.first().map(|q| q.timestamp),
+                last: bars.last().map(|q| q.timestamp),
+                covered: self.covered(&ticker, interval)?,
+                ticker,
+                interval,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Forgets the bars and fetched ranges for every interval of the ticker, but keeps its actions and info.
+    pub fn drop_bars(&self, ticker: &str) -> io::Result<usize> {
+        let mut removed = 0;
+        for entry in self.entries()? {
+            if entry.matches(Some(ticker), None) {
+                removed += self.purge(Some(ticker), Some(entry.interval))?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Whether `actions` has a split or dividend we haven't stored yet that's dated after some cached bar.
+    /// Those bars' adjusted close (and for yahoo, split-adjusted prices) were restated by it.
+    pub fn restated_by(&self, ticker: &str, actions: &CorporateActions) -> io::Result<bool> {
+        let stored = self.load_actions(ticker)?;
+        let new_dates: Vec<DateTime<Utc>> = actions
+            .dividends
+            .iter()
+            .filter(|d| !stored.dividends.contains(d))
+            .map(|d| d.ex_date)
+            .chain(
+                actions
+                    .splits
+                    .iter()
+                    .filter(|s| !stored.splits.contains(s))
+                    .map(|s| s.date),
+            )
+            .collect();
+        let latest = match new_dates.into_iter().max() {
+            Some(latest) => latest,
+            None => return Ok(false),
+        };
+        Ok(self
+            .entries()?
+            .iter()
+            .filter(|e| e.matches(Some(ticker), None))
+            .any(|e| e.first.is_some_and(|first| first < latest)))
+    }
+
+    /// Deletes cached data. None matches everything, so purge(None, None) wipes the whole cache.
+    pub fn purge(&self, ticker: Option<&str>, interval: Option<Interval>) -> io::Result<usize> {
+        let mut removed = 0;
+        for entry in self.entries()? {
+            if !entry.matches(ticker, interval) {
+                continue;
+            }
+            for ext in &["csv", "ranges"] {
+                match fs::remove_file(self.path(&entry.ticker, entry.interval, ext)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            // actions and meta are shared by every interval, so only go when the whole ticker does
+            if interval.is_none() {
+                for path in &[
+                    self.actions_path(&entry.ticker),
+                    self.meta_path(&entry.ticker),
+                ] {
+                    match fs::remove_file(path) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                        _ => {}
+                    }
+                }
+            }
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+fn parse_row(line: &str) -> Option<YQuote> {
+    let mut cols = line.split(',');
+    let mut next = || cols.next();
+    Some(YQuote {
+        timestamp: to_utc(next()?.parse().ok()?),
+        open: Decimal::from_str(next()?).ok()?,
+        high: Decimal::from_str(next()?).ok()?,
+        low: Decimal::from_str(next()?).ok()?,
+        volume: next()?.parse().ok()?,
+        close: Decimal::from_str(next()?).ok()?,
+        adjclose: Decimal::from_str(next()?).ok()?,
+    })
+}
+
+fn cache_error(e: io::Error) -> FetchError {
+    FetchError::Malformed(format!("quote cache: {}", e))
+}
+
+// ----------------------------------------------------------------------------- provider
+
+/// Serves from the cache and only asks the inner provider for the ranges it hasn't seen yet.
+/// In offline mode the inner provider is never called.
+pub struct CachedProvider<P> {
+    inner: P,
+    cache: QuoteCache,
+    offline: bool,
+}
+
+impl<P: QuoteProvider> CachedProvider<P> {
+    pub fn new(inner: P, cache: QuoteCache) -> Self {
+        Self {
+            inner,
+            cache,
+            offline: false,
+        }
+    }
+
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    async fn fill_gaps(
+        &self,
+        ticker: &str,
+        from: u64,
+        to: u64,
+        interval: Interval,
+    ) -> Result<(), FetchError> {
+        let settled = (Utc::now().timestamp() as u64).saturating_sub(SETTLE_SECS);
+        'refetch: loop {
+            let gaps = self
+                .cache
+                .missing_ranges(ticker, interval, from, to)
+                .map_err(cache_error)?;
+            for (gap_from, gap_to) in gaps {
+                let history = match self
+                    .inner
+                    .history(ticker, to_utc(gap_from), to_utc(gap_to), interval)
+                    .await
+                {
+                    Ok(history) => history,
+                    // nothing traded in the gap (weekend, holiday) - still worth remembering
+                    Err(FetchError::EmptyRange) => History::default(),
+                    Err(e) => return Err(e),
+                };
+                // A split or dividend newer than what's cached changes the older bars too. Start over -
+                // the actions are stored first, so the second time round they aren't new any more.
+                if self
+                    .cache
+                    .restated_by(ticker, &history.actions)
+                    .map_err(cache_error)?
+                {
+                    println!(
+                        "{}: new split or dividend since it was cached - fetching again",
+                        ticker
+                    );
+                    self.cache
+                        .store_actions(ticker, &history.actions)
+                        .map_err(cache_error)?;
+                    self.cache.drop_bars(ticker).map_err(cache_error)?;
+                    continue 'refetch;
+                }
+                self.cache
+                    .append(ticker, interval, &history.quotes)
+                    .map_err(cache_error)?;
+                self.cache
+                    .store_actions(ticker, &history.actions)
+                    .map_err(cache_error)?;
+                if let Some(timezone) = history.timezone {
+                    self.cache
+                        .store_timezone(ticker, timezone)
+                        .map_err(cache_error)?;
+                }
+                self.cache
+                    .mark_covered(ticker, interval, gap_from, gap_to.min(settled))
+                    .map_err(cache_error)?;
+            }
+            return Ok(());
+        }
+    }
+}
+
+fn to_utc(ts: u64) -> DateTime<Utc> {
+    Utc.timestamp(ts as i64, 0)
+}
+
+#[async_trait]
+impl<P: QuoteProvider> QuoteProvider for CachedProvider<P> {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, FetchError> {
+        if !self.offline {
+            self.fill_gaps(
+                ticker,
+                from.timestamp() as u64,
+                to.timestamp() as u64,
+                interval,
+            )
+            .await?;
+        }
+        let quotes: Data = self
+            .cache
+            .load(ticker, interval)
+            .map_err(cache_error)?
+            .into_iter()
+            .filter(|q| q.timestamp >= from && q.timestamp <= to)
+            .collect();
+        if quotes.is_empty() && self.offline {
+            return Err(FetchError::NotFound(format!(
+                "{} ({}) isn't in the cache",
+                ticker, interval
+            )));
+        }
+        let actions = self
+            .cache
+            .load_actions(ticker)
+            .map_err(cache_error)?
+            .between(from, to);
+        let timezone = self.cache.load_timezone(ticker).map_err(cache_error)?;
+        Ok(History {
+            quotes,
+            actions,
+            timezone,
+        })
+    }
+
+    /// served from the cache once fetched - names and listings hardly ever change
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        if let Some(info) = self.cache.load_info(ticker).map_err(cache_error)? {
+            return Ok(info);
+        }
+        if self.offline {
+            // caches from before metadata was kept only have the bars
+            let has_bars = self
+                .cache
+                .entries()
+                .map_err(cache_error)?
+                .iter()
+                .any(|e| e.matches(Some(ticker), None) && e.bars > 0);
+            if has_bars {
+                return Ok(TickerInfo::unknown(ticker));
+            }
+            return Err(FetchError::NotFound(format!(
+                "nothing for {} in the cache",
+                ticker
+            )));
+        }
+        let info = self.inner.info(ticker).await?;
+        self.cache.store_info(ticker, &info).map_err(cache_error)?;
+        Ok(info)
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        if self.offline {
+            return Err(FetchError::InvalidRequest(
+                "symbol search needs the network".into(),
+            ));
+        }
+        self.inner.search(query).await
+    }
+
+    fn remaining_budget(&self) -> Option<Budget> {
+        self.inner.remaining_budget()
+    }
+
+    fn split_adjusted(&self) -> bool {
+        self.inner.split_adjusted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download_data::FixtureProvider;
+
+    fn day(d: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 1, d).and_hms(14, 30, 0)
+    }
+
+    fn bar(d: u32, close: i64) -> YQuote {
+        let close = Decimal::from(close);
+        YQuote {
+            timestamp: day(d),
+            open: close,
+            high: close,
+            low: close,
+            volume: 100,
+            close,
+            adjclose: close,
+        }
+    }
+
+    fn split(d: u32) -> CorporateActions {
+        CorporateActions {
+            splits: vec![Split {
+                date: day(d),
+                numerator: 2,
+                denominator: 1,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mark_covered_merges_overlapping_and_adjacent_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = QuoteCache::new(dir.path());
+        let interval = Interval::OneDay;
+        cache.mark_covered("TEST", interval, 100, 200).unwrap();
+        cache.mark_covered("TEST", interval, 300, 400).unwrap();
+        assert_eq!(
+            cache.covered("TEST", interval).unwrap(),
+            vec![(100, 200), (300, 400)]
+        );
+        // backwards is ignored
+        cache.mark_covered("TEST", interval, 250, 240).unwrap();
+        cache.mark_covered("TEST", interval, 150, 250).unwrap();
+        cache.mark_covered("TEST", interval, 251, 299).unwrap();
+        assert_eq!(cache.covered("TEST", interval).unwrap(), vec![(100, 400)]);
+        // other intervals are kept apart
+        assert!(cache.covered("TEST", Interval::OneHour).unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_ranges_are_whats_not_covered() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = QuoteCache::new(dir.path());
+        let interval = Interval::OneDay;
+        assert_eq!(
+            cache.missing_ranges("TEST", interval, 0, 1000).unwrap(),
+            vec![(0, 1000)]
+        );
+        cache.mark_covered("TEST", interval, 100, 200).unwrap();
+        cache.mark_covered("TEST", interval, 300, 400).unwrap();
+        assert_eq!(
+            cache.missing_ranges("TEST", interval, 0, 1000).unwrap(),
+            vec![(0, 99), (201, 299), (401, 1000)]
+        );
+        assert_eq!(
+            cache.missing_ranges("TEST", interval, 150, 350).unwrap(),
+            vec![(201, 299)]
+        );
+        assert!(cache
+            .missing_ranges("TEST", interval, 120, 180)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            cache.missing_ranges("TEST", interval, 500, 600).unwrap(),
+            vec![(500, 600)]
+        );
+    }
+
+    #[test]
+    fn append_keeps_the_latest_row_per_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = QuoteCache::new(dir.path());
+        cache
+            .append("TEST", Interval::OneDay, &[bar(4, 10), bar(5, 11)])
+            .unwrap();
+        cache
+            .append("test", Interval::OneDay, &[bar(5, 12), bar(6, 13)])
+            .unwrap();
+        let closes: Vec<Decimal> = cache
+            .load("TEST", Interval::OneDay)
+            .unwrap()
+            .iter()
+            .map(|q| q.close)
+            .collect();
+        assert_eq!(closes, vec![10.into(), 12.into(), 13.into()]);
+    }
+
+    #[test]
+    fn purge_by_interval_keeps_the_shared_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = QuoteCache::new(dir.path());
+        for interval in [Interval::OneDay, Interval::OneHour] {
+            cache.append("TEST", interval, &[bar(4, 10)]).unwrap();
+            cache.mark_covered("TEST", interval, 0, 100).unwrap();
+        }
+        cache
+            .append("OTHER", Interval::OneDay, &[bar(4, 10)])
+            .unwrap();
+        cache.store_actions("TEST", &split(5)).unwrap();
+        cache
+            .store_timezone("TEST", chrono_tz::America::New_York)
+            .unwrap();
+
+        assert_eq!(
+            cache.purge(Some("test"), Some(Interval::OneHour)).unwrap(),
+            1
+        );
+        assert!(cache.load("TEST", Interval::OneHour).unwrap().is_empty());
+        assert!(cache.covered("TEST", Interval::OneHour).unwrap().is_empty());
+        assert_eq!(cache.load("TEST", Interval::OneDay).unwrap().len(), 1);
+        assert_eq!(cache.load_actions("TEST").unwrap(), split(5));
+        assert!(cache.load_timezone("TEST").unwrap().is_some());
+
+        assert_eq!(cache.purge(Some("TEST"), None).unwrap(), 1);
+        assert!(cache.load_actions("TEST").unwrap().is_empty());
+        assert!(cache.load_timezone("TEST").unwrap().is_none());
+        assert_eq!(cache.entries().unwrap().len(), 1);
+
+        assert_eq!(cache.purge(None, None).unwrap(), 1);
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_newer_actions_restate_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = QuoteCache::new(dir.path());
+        assert!(!cache.restated_by("TEST", &split(5)).unwrap());
+        cache
+            .append("TEST", Interval::OneDay, &[bar(4, 10), bar(5, 10)])
+            .unwrap();
+        assert!(cache.restated_by("TEST", &split(5)).unwrap());
+        // older than anything cached, or already known
+        assert!(!cache.restated_by("TEST", &split(4)).unwrap());
+        cache.store_actions("TEST", &split(5)).unwrap();
+        assert!(!cache.restated_by("TEST", &split(5)).unwrap());
+    }
+
+    #[async_std::test]
+    async fn a_new_split_refetches_the_cached_bars() {
+        let dir = tempfile::tempdir().unwrap();
+        let before = FixtureProvider::new().with_quotes("TEST", vec![bar(4, 10), bar(5, 10)]);
+        CachedProvider::new(before, QuoteCache::new(dir.path()))
+            .history("TEST", day(4), day(5), Interval::OneDay)
+            .await
+            .unwrap();
+
+        // the vendor has since restated everything before the split
+        let after = FixtureProvider::new()
+            .with_quotes("TEST", vec![bar(4, 5), bar(5, 5), bar(11, 5), bar(12, 6)])
+            .with_actions("TEST", split(11));
+        let history = CachedProvider::new(after, QuoteCache::new(dir.path()))
+            .history("TEST", day(4), day(12), Interval::OneDay)
+            .await
+            .unwrap();
+        let closes: Vec<Decimal> = history.quotes.iter().map(|q| q.close).collect();
+        assert_eq!(closes, vec![5.into(), 5.into(), 5.into(), 6.into()]);
+        assert_eq!(history.actions, split(11));
+    }
+}
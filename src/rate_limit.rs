@@ -0,0 +1,326 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::download_data::{FetchError, History, QuoteProvider};
+use crate::interval::Interval;
+use crate::metadata::{SearchResult, TickerInfo};
+
+// ----------------------------------------------------------------------------- clock
+
+/// Time source for the limiter. Swap in MockClock to test without actually waiting.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    /// time elapsed since some fixed starting point
+    fn now(&self) -> Duration;
+    async fn sleep(&self, d: Duration);
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    async fn sleep(&self, d: Duration) {
+        async_std::task::sleep(d).await
+    }
+}
+
+/// Clock that only moves when told to - sleeping just fast-forwards it.
+#[derive(Default)]
+pub struct MockClock {
+    now: Mutex<Duration>,
+}
+
+impl MockClock {
+    pub fn advance(&self, d: Duration) {
+        *self.now.lock().unwrap() += d;
+    }
+}
+
+#[async_trait]
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+
+    async fn sleep(&self, d: Duration) {
+        self.advance(d)
+    }
+}
+
+// ----------------------------------------------------------------------------- limiter
+
+/// Request budgets, each at least 1. None = unlimited for that period.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+    pub per_hour: Option<u32>,
+    pub per_day: Option<u32>,
+}
+
+/// yahoo's public api: 2,000 requests per hour per IP, up to 48,000 a day
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_hour: Some(2_000),
+            per_day: Some(48_000),
+        }
+    }
+}
+
+/// Requests left in each budget right now. None = that budget isn't limited.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Budget {
+    pub hourly: Option<u32>,
+    pub daily: Option<u32>,
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |b: Option<u32>| b.map_or("unlimited".to_string(), |b| b.to_string());
+        write!(f, "{}/h, {}/day", show(self.hourly), show(self.daily))
+    }
+}
+
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    /// tokens per second
+    refill_rate: f64,
+    last_refill: Duration,
+}
+
+impl TokenBucket {
+    fn new(capacity: u32, period: Duration, now: Duration) -> Self {
+        Self {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            refill_rate: capacity as f64 / period.as_secs_f64(),
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Duration) {
+        let elapsed = now.saturating_sub(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// how long until a whole token is available
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.refill_rate)
+        }
+    }
+}
+
+/// Token bucket limiter shared (via Arc) by everyone hitting the same provider.
+pub struct RateLimiter {
+    clock: Arc<dyn Clock>,
+    hourly: Option<Mutex<TokenBucket>>,
+    daily: Option<Mutex<TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock::default()))
+    }
+
+    pub fn with_clock(config: RateLimitConfig, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        let bucket = |budget: Option<u32>, secs: u64| {
+            budget.map(|b| Mutex::new(TokenBucket::new(b, Duration::from_secs(secs), now)))
+        };
+        Self {
+            hourly: bucket(config.per_hour, 60 * 60),
+            daily: bucket(config.per_day, 24 * 60 * 60),
+            clock,
+        }
+    }
+
+    fn buckets(&self) -> impl Iterator<Item = &Mutex<TokenBucket>> {
+        self.hourly.iter().chain(self.daily.iter())
+    }
+
+    /// Takes a token from every budget if all of them have one, otherwise returns how long to wait.
+    fn take(&self) -> Result<(), Duration> {
+        let now = self.clock.now();
+        let mut buckets: Vec<_> = self.buckets().map(|b| b.lock().unwrap()).collect();
+        buckets.iter_mut().for_each(|b| b.refill(now));
+        let wait = buckets
+            .iter()
+            .map(|b| b.wait_time())
+            .max()
+            .unwrap_or_default();
+        if wait > Duration::from_secs(0) {
+            return Err(wait);
+        }
+        buckets.iter_mut().for_each(|b| b.tokens -= 1.0);
+        Ok(())
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.take().is_ok()
+    }
+
+    /// Waits (without blocking the executor) until a request is allowed.
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.take() {
+            self.clock.sleep(wait).await;
+        }
+    }
+
+    pub fn remaining(&self) -> Budget {
+        let now = self.clock.now();
+        let left = |bucket: &Option<Mutex<TokenBucket>>| {
+            bucket.as_ref().map(|b| {
+                let mut b = b.lock().unwrap();
+                b.refill(now);
+                b.tokens.floor() as u32
+            })
+        };
+        Budget {
+            hourly: left(&self.hourly),
+            daily: left(&self.daily),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------- provider
+
+/// Wraps a provider so every request first takes a token from the limiter.
+pub struct RateLimitedProvider<P> {
+    inner: P,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<P: QuoteProvider> RateLimitedProvider<P> {
+    pub fn new(inner: P, limiter: Arc<RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl<P: QuoteProvider> QuoteProvider for RateLimitedProvider<P> {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, FetchError> {
+        self.limiter.acquire().await;
+        self.inner.history(ticker, from, to, interval).await
+    }
+
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        // yahoo needs a chart and a search request to fill everything in
+        self.limiter.acquire().await;
+        self.limiter.acquire().await;
+        self.inner.info(ticker).await
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        self.limiter.acquire().await;
+        self.inner.search(query).await
+    }
+
+    fn remaining_budget(&self) -> Option<Budget> {
+        Some(self.limiter.remaining())
+    }
+
+    fn split_adjusted(&self) -> bool {
+        self.inner.split_adjusted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_hour: u32, per_day: Option<u32>) -> (RateLimiter, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::default());
+        let config = RateLimitConfig {
+            per_hour: Some(per_hour),
+            per_day,
+        };
+        (RateLimiter::with_clock(config, clock.clone()), clock)
+    }
+
+    #[test]
+    fn starts_full_and_runs_dry() {
+        let (limiter, _) = limiter(3, None);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn refills_at_the_hourly_rate() {
+        // 2 an hour = one every 30 minutes
+        let (limiter, clock) = limiter(2, None);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        clock.advance(Duration::from_secs(29 * 60));
+        assert!(!limiter.try_acquire());
+        clock.advance(Duration::from_secs(60));
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn refill_stops_at_capacity() {
+        let (limiter, clock) = limiter(2, None);
+        clock.advance(Duration::from_secs(24 * 60 * 60));
+        assert_eq!(limiter.remaining().hourly, Some(2));
+    }
+
+    #[test]
+    fn every_budget_has_to_allow_it() {
+        let (limiter, clock) = limiter(10, Some(2));
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        assert_eq!(
+            limiter.remaining(),
+            Budget {
+                hourly: Some(8),
+                daily: Some(0)
+            }
+        );
+        // a daily token comes back every 12 hours
+        clock.advance(Duration::from_secs(12 * 60 * 60));
+        assert!(limiter.try_acquire());
+    }
+
+    #[async_std::test]
+    async fn acquire_waits_for_the_next_token() {
+        let (limiter, clock) = limiter(2, None);
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert_eq!(clock.now(), Duration::from_secs(0));
+        limiter.acquire().await;
+        let waited = clock.now().as_secs_f64();
+        assert!((waited - 30.0 * 60.0).abs() < 1e-6, "waited {}s", waited);
+        assert_eq!(limiter.remaining().hourly, Some(0));
+    }
+}
@@ -0,0 +1,240 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+use crate::download_data::{FetchError, History, QuoteProvider};
+use crate::interval::Interval;
+use crate::metadata::{SearchResult, TickerInfo};
+use crate::rate_limit::{Budget, Clock, SystemClock};
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// retries on top of the first attempt
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff ceiling for the nth retry (0-based), before jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exp = self.base_delay.as_secs_f64() * 2f64.powi(retry as i32);
+        Duration::from_secs_f64(exp.min(self.max_delay.as_secs_f64()))
+    }
+
+    /// "Full jitter" - anywhere between 0 and the backoff ceiling, so retrying downloaders don't all wake up together.
+    pub fn jittered_backoff(&self, retry: u32, rng: &mut impl Rng) -> Duration {
+        self.backoff(retry).mul_f64(rng.gen_range(0.0..=1.0))
+    }
+}
+
+/// Wraps a provider and retries transient failures (network, rate limits).
+/// Permanent ones (not found, malformed, empty) are handed straight back.
+pub struct RetryingProvider<P> {
+    inner: P,
+    policy: RetryPolicy,
+    clock: Arc<dyn Clock>,
+}
+
+impl<P: QuoteProvider> RetryingProvider<P> {
+    pub fn new(inner: P, policy: RetryPolicy) -> Self {
+        Self::with_clock(inner, policy, Arc::new(SystemClock::default()))
+    }
+
+    pub fn with_clock(inner: P, policy: RetryPolicy, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            policy,
+            clock,
+        }
+    }
+
+    /// Runs `attempt` until it works, fails for good or runs out of retries. `what` is only for the log line.
+    async fn retrying<T, F, Fut>(&self, what: &str, attempt: F) -> Result<T, FetchError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, FetchError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match attempt().await {
+                Err(e) if e.is_transient() && retry < self.policy.max_retries => {
+                    // rng isn't Send, so it can't live across the await
+                    let delay = self.policy.jittered_backoff(retry, &mut rand::thread_rng());
+                    println!(
+                        "{}: {} - retrying in {:.1}s ({}/{})",
+                        what,
+                        e,
+                        delay.as_secs_f64(),
+                        retry + 1,
+                        self.policy.max_retries
+                    );
+                    self.clock.sleep(delay).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<P: QuoteProvider> QuoteProvider for RetryingProvider<P> {
+    async fn history(
+        &self,
+        ticker: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: Interval,
+    ) -> Result<History, FetchError> {
+        self.retrying(ticker, || self.inner.history(ticker, from, to, interval))
+            .await
+    }
+
+    async fn info(&self, ticker: &str) -> Result<TickerInfo, FetchError> {
+        self.retrying(ticker, || self.inner.info(ticker)).await
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>, FetchError> {
+        self.retrying(query, || self.inner.search(query)).await
+    }
+
+    fn remaining_budget(&self) -> Option<Budget> {
+        self.inner.remaining_budget()
+    }
+
+    fn split_adjusted(&self) -> bool {
+        self.inner.split_adjusted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::rate_limit::MockClock;
+
+    /// Hands out the scripted errors in order, then succeeds. Counts every call.
+    #[derive(Default)]
+    struct Flaky {
+        errors: Mutex<Vec<FetchError>>,
+        calls: Arc<AtomicU32>,
+    }
+
+    impl Flaky {
+        fn failing(mut errors: Vec<FetchError>) -> Self {
+            errors.reverse();
+            Self {
+                errors: Mutex::new(errors),
+                ..Self::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl QuoteProvider for Flaky {
+        async fn history(
+            &self,
+            _ticker: &str,
+            _from: DateTime<Utc>,
+            _to: DateTime<Utc>,
+            _interval: Interval,
+        ) -> Result<History, FetchError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.errors.lock().unwrap().pop() {
+                Some(e) => Err(e),
+                None => Ok(History::default()),
+            }
+        }
+    }
+
+    struct Fixture {
+        provider: RetryingProvider<Flaky>,
+        calls: Arc<AtomicU32>,
+        clock: Arc<MockClock>,
+    }
+
+    fn retrying(errors: Vec<FetchError>) -> Fixture {
+        let inner = Flaky::failing(errors);
+        let calls = inner.calls.clone();
+        let clock = Arc::new(MockClock::default());
+        let provider = RetryingProvider::with_clock(inner, RetryPolicy::default(), clock.clone());
+        Fixture {
+            provider,
+            calls,
+            clock,
+        }
+    }
+
+    impl Fixture {
+        fn calls(&self) -> u32 {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    async fn fetch(provider: &RetryingProvider<Flaky>) -> Result<History, FetchError> {
+        provider
+            .history("TEST", Utc::now(), Utc::now(), Interval::OneDay)
+            .await
+    }
+
+    #[async_std::test]
+    async fn transient_errors_are_retried() {
+        let t = retrying(vec![
+            FetchError::RateLimited,
+            FetchError::Network("reset".into()),
+        ]);
+        assert!(fetch(&t.provider).await.is_ok());
+        assert_eq!(t.calls(), 3);
+        // backed off in between, on the mock clock
+        let policy = RetryPolicy::default();
+        assert!(t.clock.now() <= policy.backoff(0) + policy.backoff(1));
+    }
+
+    #[async_std::test]
+    async fn gives_up_after_max_retries() {
+        let t = retrying(vec![FetchError::RateLimited; 10]);
+        assert_eq!(fetch(&t.provider).await, Err(FetchError::RateLimited));
+        // the first attempt plus 4 retries
+        assert_eq!(t.calls(), 5);
+    }
+
+    #[async_std::test]
+    async fn permanent_errors_come_straight_back() {
+        for e in [
+            FetchError::NotFound("TEST".into()),
+            FetchError::InvalidRequest("bad range".into()),
+            FetchError::EmptyRange,
+            FetchError::Malformed("truncated".into()),
+        ] {
+            let t = retrying(vec![e.clone(), e.clone()]);
+            assert_eq!(fetch(&t.provider).await, Err(e));
+            assert_eq!(t.calls(), 1);
+            assert_eq!(t.clock.now(), Duration::from_secs(0));
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10), Duration::from_secs(30));
+    }
+}
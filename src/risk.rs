@@ -0,0 +1,405 @@
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::indicators::{ln_ratio, rolling_std_dev};
+use crate::interval::Interval;
+
+/// Knobs for the risk numbers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RiskConfig {
+    /// annual, as a fraction - 0.04 for 4%
+    pub risk_free_rate: Decimal,
+    /// bars in the rolling volatility window. None = 30 calendar days' worth, like the 30d avg
+    pub window: Option<usize>,
+}
+
+/// Return and risk numbers for one price series. Everything annualised is per the interval's bars per year.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RiskStats {
+    /// bar to bar simple returns, one fewer than the prices
+    pub returns: Vec<Decimal>,
+    /// bar to bar log returns
+    pub log_returns: Vec<Decimal>,
+    /// compounded growth per year over the whole range
+    pub annualized_return: Option<Decimal>,
+    /// annualised sample standard deviation of the returns
+    pub volatility: Option<Decimal>,
+    /// annualised volatility over a rolling window, lined up with the end of the returns
+    pub rolling_volatility: Option<Vec<Decimal>>,
+    pub sharpe: Option<Decimal>,
+    /// like sharpe, but only the returns below the risk-free rate count as risk
+    pub sortino: Option<Decimal>,
+    pub skew: Option<Decimal>,
+    /// excess kurtosis - 0 for a normal distribution
+    pub kurtosis: Option<Decimal>,
+    /// (percent, return) for the 5th, 25th, 50th, 75th and 95th percentile of the returns
+    pub percentiles: Vec<(Decimal, Decimal)>,
+}
+
+/// (price now - price before) / price before, for every pair of neighbouring bars
+pub fn simple_returns(series: &[Decimal]) -> Vec<Decimal> {
+    series
+        .windows(2)
+        .map(|w| {
+            if w[0].is_zero() {
+                Decimal::zero()
+            } else {
+                w[1] / w[0] - Decimal::one()
+            }
+        })
+        .collect()
+}
+
+/// ln(price now / price before), for every pair of neighbouring bars
+pub fn log_returns(series: &[Decimal]) -> Vec<Decimal> {
+    series.windows(2).map(|w| ln_ratio(w[1], w[0])).collect()
+}
+
+pub(crate) fn mean(values: &[Decimal]) -> Option<Decimal> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<Decimal>() / Decimal::from(values.len()))
+}
+
+/// sample standard deviation, n - 1 denominator
+pub(crate) fn std_dev(values: &[Decimal]) -> Option<Decimal> {
+    covariance(values, values)?.sqrt()
+}
+
+/// Sample covariance (n - 1 denominator) of two series going value for value.
+pub fn covariance(x: &[Decimal], y: &[Decimal]) -> Option<Decimal> {
+    let len = x.len().min(y.len());
+    if len < 2 {
+        return None;
+    }
+    let (x, y) = (&x[..len], &y[..len]);
+    let (mean_x, mean_y) = (mean(x)?, mean(y)?);
+    let sum: Decimal = x
+        .iter()
+        .zip(y)
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    Some(sum / Decimal::from(len - 1))
+}
+
+/// Pearson correlation of two series going value for value. None when either one is flat.
+pub fn correlation(x: &[Decimal], y: &[Decimal]) -> Option<Decimal> {
+    let len = x.len().min(y.len());
+    let (sd_x, sd_y) = (std_dev(&x[..len])?, std_dev(&y[..len])?);
+    if sd_x.is_zero() || sd_y.is_zero() {
+        return None;
+    }
+    Some(covariance(x, y)? / (sd_x * sd_y))
+}
+
+/// the k-th central moment, n denominator
+fn central_moment(values: &[Decimal], k: u64) -> Option<Decimal> {
+    let mean = mean(values)?;
+    let sum: Decimal = values.iter().map(|v| (v - mean).powu(k)).sum();
+    Some(sum / Decimal::from(values.len()))
+}
+
+pub(crate) fn sqrt_bars_per_year(interval: Interval) -> Decimal {
+    Decimal::from_f64(interval.bars_per_year().sqrt()).unwrap_or_else(Decimal::one)
+}
+
+/// Volatility per year from per-bar returns.
+pub fn annualized_volatility(returns: &[Decimal], interval: Interval) -> Option<Decimal> {
+    Some(std_dev(returns)? * sqrt_bars_per_year(interval))
+}
+
+/// Annualised volatility over each window of n returns. len - n + 1 values.
+pub fn rolling_volatility(
+    n: usize,
+    returns: &[Decimal],
+    interval: Interval,
+) -> Option<Vec<Decimal>> {
+    let scale = sqrt_bars_per_year(interval);
+    Some(
+        rolling_std_dev(n, returns)?
+            .into_iter()
+            .map(|v| v * scale)
+            .collect(),
+    )
+}
+
+/// Mean excess return over its standard deviation, annualised. `risk_free_rate` is per year.
+pub fn sharpe(returns: &[Decimal], risk_free_rate: Decimal, interval: Interval) -> Option<Decimal> {
+    let rf = per_bar(risk_free_rate, interval);
+    let excess: Vec<Decimal> = returns.iter().map(|r| r - rf).collect();
+    let sd = std_dev(&excess)?;
+    if sd.is_zero() {
+        return None;
+    }
+    Some(mean(&excess)? / sd * sqrt_bars_per_year(interval))
+}
+
+/// Mean excess return over the downside deviation (root mean square of the shortfalls below the
+/// risk-free rate, over every bar), annualised.
+pub fn sortino(
+    returns: &[Decimal],
+    risk_free_rate: Decimal,
+    interval: Interval,
+) -> Option<Decimal> {
+    let rf = per_bar(risk_free_rate, interval);
+    let excess: Vec<Decimal> = returns.iter().map(|r| r - rf).collect();
+    let shortfalls: Decimal = excess
+        .iter()
+        .map(|e| (*e).min(Decimal::zero()))
+        .map(|e| e * e)
+        .sum();
+    let downside = (shortfalls / Decimal::from(excess.len().max(1))).sqrt()?;
+    if downside.is_zero() {
+        return None;
+    }
+    Some(mean(&excess)? / downside * sqrt_bars_per_year(interval))
+}
+
+/// Skewness, m3 / m2^1.5 - negative = a fatter left tail.
+pub fn skew(returns: &[Decimal]) -> Option<Decimal> {
+    let m2 = central_moment(returns, 2)?;
+    if m2.is_zero() {
+        return None;
+    }
+    Some(central_moment(returns, 3)? / (m2 * m2.sqrt()?))
+}
+
+/// Excess kurtosis, m4 / m2^2 - 3.
+pub fn kurtosis(returns: &[Decimal]) -> Option<Decimal> {
+    let m2 = central_moment(returns, 2)?;
+    if m2.is_zero() {
+        return None;
+    }
+    Some(central_moment(returns, 4)? / (m2 * m2) - Decimal::from(3))
+}
+
+/// The p-th percentile (0..100), interpolating linearly between the two closest ranks.
+pub fn percentile(values: &[Decimal], p: Decimal) -> Option<Decimal> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort();
+    let rank = p.max(Decimal::zero()).min(Decimal::from(100)) / Decimal::from(100)
+        * Decimal::from(sorted.len() - 1);
+    let lower = rank.floor().to_usize()?;
+    let upper = rank.ceil().to_usize()?;
+    let fraction = rank - rank.floor();
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * fraction)
+}
+
+/// annual rate -> simple rate per bar
+pub(crate) fn per_bar(rate: Decimal, interval: Interval) -> Decimal {
+    rate / Decimal::from_f64(interval.bars_per_year()).unwrap_or_else(Decimal::one)
+}
+
+impl RiskStats {
+    /// names of the summary columns `row` fills in
+    pub fn columns() -> Vec<String> {
+        [
+            "ann return %",
+            "volatility %",
+            "rolling vol %",
+            "sharpe",
+            "sortino",
+            "skew",
+            "kurtosis",
+            "p5 return %",
+            "p95 return %",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+    }
+
+    pub fn compute(series: &[Decimal], interval: Interval, config: &RiskConfig) -> Self {
+        let returns = simple_returns(series);
+        let log_returns = log_returns(series);
+        let window = config
+            .window
+            .unwrap_or_else(|| interval.bars_in(chrono::Duration::days(30)));
+        let percentiles = [5, 25, 50, 75, 95]
+            .iter()
+            .filter_map(|p| Some((Decimal::from(*p), percentile(&returns, Decimal::from(*p))?)))
+            .collect();
+        Self {
+            annualized_return: annualized_return(series, interval),
+            volatility: annualized_volatility(&returns, interval),
+            rolling_volatility: rolling_volatility(window, &returns, interval),
+            sharpe: sharpe(&returns, config.risk_free_rate, interval),
+            sortino: sortino(&returns, config.risk_free_rate, interval),
+            skew: skew(&returns),
+            kurtosis: kurtosis(&returns),
+            percentiles,
+            returns,
+            log_returns,
+        }
+    }
+
+    fn percentile_of(&self, p: i64) -> Option<Decimal> {
+        self.percentiles
+            .iter()
+            .find(|(pct, _)| *pct == Decimal::from(p))
+            .map(|(_, value)| *value)
+    }
+
+    /// the summary columns, rounded - blank where there weren't enough bars
+    pub fn row(&self) -> Vec<String> {
+        let pct = |v: Option<Decimal>| v.map(|v| v * Decimal::from(100));
+        [
+            pct(self.annualized_return),
+            pct(self.volatility),
+            pct(self
+                .rolling_volatility
+                .as_ref()
+                .and_then(|v| v.last().copied())),
+            self.sharpe,
+            self.sortino,
+            self.skew,
+            self.kurtosis,
+            pct(self.percentile_of(5)),
+            pct(self.percentile_of(95)),
+        ]
+        .iter()
+        .map(|v| v.map_or(String::new(), |v| v.round_dp(2).to_string()))
+        .collect()
+    }
+}
+
+/// Compounded growth per year, (last / first)^(bars per year / bars) - 1.
+pub fn annualized_return(series: &[Decimal], interval: Interval) -> Option<Decimal> {
+    let (first, last) = (series.first()?.to_f64()?, series.last()?.to_f64()?);
+    if series.len() < 2 || first <= 0.0 || last <= 0.0 {
+        return None;
+    }
+    let years = (series.len() - 1) as f64 / interval.bars_per_year();
+    Decimal::from_f64((last / first).powf(1.0 / years) - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    fn assert_close(got: Option<Decimal>, expected: f64) {
+        let got = got.unwrap().to_f64().unwrap();
+        assert!((got - expected).abs() < 1e-9, "{} vs {}", got, expected);
+    }
+
+    // Monthly returns of 2%, -1%, 4%, -1%, from prices 100, 102, 100.98, 105.0192, 103.969008.
+    // mean 1%, deviations 1, -2, 3, -2 (in %), so the sum of squares is 18 (%^2):
+    //   sample sd = sqrt(18 / 3) = sqrt(6)%, population m2 = 4.5, m3 = (1 - 8 + 27 - 8) / 4 = 3,
+    //   m4 = (1 + 16 + 81 + 16) / 4 = 28.5
+    fn prices() -> Vec<Decimal> {
+        ["100", "102", "100.98", "105.0192", "103.969008"]
+            .iter()
+            .map(|p| dec(p))
+            .collect()
+    }
+
+    fn returns() -> Vec<Decimal> {
+        simple_returns(&prices())
+    }
+
+    #[test]
+    fn returns_from_prices() {
+        assert_eq!(
+            returns(),
+            vec![dec("0.02"), dec("-0.01"), dec("0.04"), dec("-0.01")]
+        );
+        assert_close(log_returns(&prices()).first().copied(), 1.02f64.ln());
+    }
+
+    #[test]
+    fn sharpe_by_hand() {
+        // 1 / sqrt(6) per month, sqrt(12) months a year - sqrt(2)
+        assert_close(
+            sharpe(&returns(), Decimal::zero(), Interval::OneMonth),
+            2f64.sqrt(),
+        );
+        // 12% a year is 1% a month, which takes the whole mean away
+        assert_close(sharpe(&returns(), dec("0.12"), Interval::OneMonth), 0.0);
+        assert_eq!(
+            sharpe(&[dec("0.01"); 3], Decimal::zero(), Interval::OneMonth),
+            None
+        );
+    }
+
+    #[test]
+    fn sortino_by_hand() {
+        // two -1% shortfalls over 4 bars: downside = sqrt(2 / 4)%, 1 / sqrt(0.5) * sqrt(12) = sqrt(24)
+        assert_close(
+            sortino(&returns(), Decimal::zero(), Interval::OneMonth),
+            24f64.sqrt(),
+        );
+        // nothing below the risk-free rate
+        assert_eq!(
+            sortino(
+                &[dec("0.01"), dec("0.02")],
+                Decimal::zero(),
+                Interval::OneMonth
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn skew_and_kurtosis_by_hand() {
+        // 3 / 4.5^1.5 and 28.5 / 4.5^2 - 3
+        assert_close(skew(&returns()), 3.0 / 4.5f64.powf(1.5));
+        assert_close(kurtosis(&returns()), 28.5 / 20.25 - 3.0);
+        assert_eq!(skew(&[dec("0.01"); 4]), None);
+        assert_eq!(kurtosis(&[]), None);
+    }
+
+    #[test]
+    fn percentiles_interpolate_between_ranks() {
+        // sorted -1 -1 2 4 (%), ranks 0..3
+        let returns = returns();
+        let at = |p: &str| percentile(&returns, dec(p)).unwrap();
+        assert_eq!(at("0"), dec("-0.01"));
+        assert_eq!(at("25"), dec("-0.01"));
+        // rank 1.5, halfway from -1% to 2%
+        assert_eq!(at("50"), dec("0.005"));
+        // rank 2.7
+        assert_eq!(at("90"), dec("0.034"));
+        assert_eq!(at("100"), dec("0.04"));
+        assert_eq!(at("150"), dec("0.04"));
+        assert_eq!(percentile(&[], dec("50")), None);
+    }
+
+    #[test]
+    fn annualized_return_compounds() {
+        // 12 monthly bars from 100 to 110 is a year at 10%, 24 from 100 to 121 two more years of it
+        let mut year = vec![Decimal::from(100); 12];
+        year.push(Decimal::from(110));
+        assert_close(annualized_return(&year, Interval::OneMonth), 0.1);
+        let mut two_years = vec![Decimal::from(100); 24];
+        two_years.push(Decimal::from(121));
+        assert_close(annualized_return(&two_years, Interval::OneMonth), 0.1);
+        assert_eq!(annualized_return(&year[..1], Interval::OneMonth), None);
+        assert_eq!(
+            annualized_return(&[Decimal::zero(), Decimal::one()], Interval::OneMonth),
+            None
+        );
+    }
+
+    #[test]
+    fn stats_put_together() {
+        let config = RiskConfig {
+            risk_free_rate: Decimal::zero(),
+            window: Some(2),
+        };
+        let stats = RiskStats::compute(&prices(), Interval::OneMonth, &config);
+        assert_close(stats.sharpe, 2f64.sqrt());
+        assert_eq!(stats.rolling_volatility.as_ref().map(|v| v.len()), Some(3));
+        assert_eq!(stats.percentiles[2], (Decimal::from(50), dec("0.005")));
+        assert_eq!(stats.row().len(), RiskStats::columns().len());
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::download_data::YQuote;
+
+/// Which number out of a bar an indicator follows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PriceField {
+    Open,
+    High,
+    Low,
+    Close,
+    #[default]
+    AdjClose,
+    Volume,
+}
+
+impl PriceField {
+    /// the field out of every bar
+    pub fn series(&self, quotes: &[YQuote]) -> Vec<Decimal> {
+        quotes.iter().map(|q| self.of(q)).collect()
+    }
+
+    pub fn of(&self, q: &YQuote) -> Decimal {
+        match self {
+            PriceField::Open => q.open,
+            PriceField::High => q.high,
+            PriceField::Low => q.low,
+            PriceField::Close => q.close,
+            PriceField::AdjClose => q.adjclose,
+            PriceField::Volume => Decimal::from(q.volume),
+        }
+    }
+}
+
+impl fmt::Display for PriceField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PriceField::Open => "open",
+            PriceField::High => "high",
+            PriceField::Low => "low",
+            PriceField::Close => "close",
+            PriceField::AdjClose => "adjclose",
+            PriceField::Volume => "volume",
+        })
+    }
+}
+
+impl FromStr for PriceField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "open" => Ok(PriceField::Open),
+            "high" => Ok(PriceField::High),
+            "low" => Ok(PriceField::Low),
+            "close" => Ok(PriceField::Close),
+            "adjclose" => Ok(PriceField::AdjClose),
+            "volume" => Ok(PriceField::Volume),
+            _ => Err(format!(
+                "unknown field '{}' - use open, high, low, close, adjclose or volume",
+                s
+            )),
+        }
+    }
+}
+
+/// An indicator that keeps its own state and takes one value at a time, so a new bar costs O(1)
+/// instead of a rerun over the whole history.
+pub trait StreamingIndicator {
+    type Output;
+
+    /// Feeds the next value. None while still warming up.
+    fn push(&mut self, value: Decimal) -> Option<Self::Output>;
+
+    /// the bar field `update` reads
+    fn field(&self) -> PriceField;
+
+    fn update(&mut self, quote: &YQuote) -> Option<Self::Output> {
+        self.push(self.field().of(quote))
+    }
+}
+
+/// Runs a fresh indicator over a whole series, keeping only the warmed-up outputs.
+/// This is what the batch functions are built on, so both modes agree to the last digit.
+pub fn run<I: StreamingIndicator>(mut indicator: I, series: &[Decimal]) -> Vec<I::Output> {
+    series.iter().filter_map(|v| indicator.push(*v)).collect()
+}
+
+// ----------------------------------------------------------------------------- sma
+
+/// Simple moving average over the last n values, kept as a running sum.
+#[derive(Clone, Debug)]
+pub struct RollingSma {
+    n: usize,
+    window: VecDeque<Decimal>,
+    sum: Decimal,
+    field: PriceField,
+}
+
+impl RollingSma {
+    pub fn new(n: usize) -> Self {
+        Self {
+            n: n.max(1),
+            window: VecDeque::with_capacity(n),
+            sum: Decimal::zero(),
+            field: PriceField::default(),
+        }
+    }
+
+    pub fn on(mut self, field: PriceField) -> Self {
+        self.field = field;
+        self
+    }
+}
+
+impl StreamingIndicator for RollingSma {
+    type Output = Decimal;
+
+    fn push(&mut self, value: Decimal) -> Option<Decimal> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.n {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        if self.window.len() < self.n {
+            return None;
+        }
+        Some(self.sum / Decimal::from(self.n))
+    }
+
+    fn field(&self) -> PriceField {
+        self.field
+    }
+}
+
+// ----------------------------------------------------------------------------- ema
+
+/// Exponential moving average seeded with the sma of the first n values.
+#[derive(Clone, Debug)]
+pub struct StreamingEma {
+    alpha: Decimal,
+    seed: RollingSma,
+    value: Option<Decimal>,
+    field: PriceField,
+}
+
+impl StreamingEma {
+    pub fn new(n: usize) -> Self {
+        Self {
+            alpha: Decimal::from(2) / Decimal::from(n.max(1) + 1),
+            seed: RollingSma::new(n),
+            value: None,
+            field: PriceField::default(),
+        }
+    }
+
+    pub fn on(mut self, field: PriceField) -> Self {
+        self.field = field;
+        self
+    }
+}
+
+impl StreamingIndicator for StreamingEma {
+    type Output = Decimal;
+
+    fn push(&mut self, value: Decimal) -> Option<Decimal> {
+        let next = match self.value {
+            Some(prev) => prev + self.alpha * (value - prev),
+            None => self.seed.push(value)?,
+        };
+        self.value = Some(next);
+        self.value
+    }
+
+    fn field(&self) -> PriceField {
+        self.field
+    }
+}
+
+// ----------------------------------------------------------------------------- min / max
+
+/// Min and max over the last n values. Two monotonic deques hold the only values that can still
+/// become the min / max, so each push is amortised O(1) however big the window is.
+#[derive(Clone, Debug)]
+pub struct RollingMinMax {
+    /// None = everything seen so far
+    n: Option<usize>,
+    seen: usize,
+    /// (index, value), values increasing front to back
+    mins: VecDeque<(usize, Decimal)>,
+    /// (index, value), values decreasing front to back
+    maxs: VecDeque<(usize, Decimal)>,
+    field: PriceField,
+}
+
+impl RollingMinMax {
+    pub fn new(n: usize) -> Self {
+        Self::with_window(Some(n.max(1)))
+    }
+
+    /// running min / max since the first value
+    pub fn expanding() -> Self {
+        Self::with_window(None)
+    }
+
+    fn with_window(n: Option<usize>) -> Self {
+        Self {
+            n,
+            seen: 0,
+            mins: VecDeque::new(),
+            maxs: VecDeque::new(),
+            field: PriceField::default(),
+        }
+    }
+
+    pub fn on(mut self, field: PriceField) -> Self {
+        self.field = field;
+        self
+    }
+}
+
+impl StreamingIndicator for RollingMinMax {
+    /// (min, max)
+    type Output = (Decimal, Decimal);
+
+    fn push(&mut self, value: Decimal) -> Option<(Decimal, Decimal)> {
+        let i = self.seen;
+        self.seen += 1;
+        while self.mins.back().is_some_and(|(_, v)| *v >= value) {
+            self.mins.pop_back();
+        }
+        self.mins.push_back((i, value));
+        while self.maxs.back().is_some_and(|(_, v)| *v <= value) {
+            self.maxs.pop_back();
+        }
+        self.maxs.push_back((i, value));
+
+        if let Some(n) = self.n {
+            // drop whatever slid out of the window
+            while self.mins.front().is_some_and(|(j, _)| j + n <= i) {
+                self.mins.pop_front();
+            }
+            while self.maxs.front().is_some_and(|(j, _)| j + n <= i) {
+                self.maxs.pop_front();
+            }
+            if self.seen < n {
+                return None;
+            }
+        }
+        Some((self.mins.front()?.1, self.maxs.front()?.1))
+    }
+
+    fn field(&self) -> PriceField {
+        self.field
+    }
+}
+
+// ----------------------------------------------------------------------------- variance
+
+/// Variance over the last n values, from a running sum and sum of squares - sample (n - 1 denominator)
+/// by default. Decimal keeps both exact, so there's none of the cancellation trouble the f64 version has.
+#[derive(Clone, Debug)]
+pub struct RollingVariance {
+    n: usize,
+    population: bool,
+    window: VecDeque<Decimal>,
+    sum: Decimal,
+    sum_sq: Decimal,
+    field: PriceField,
+}
+
+impl RollingVariance {
+    /// n has to be at least 2 for a sample variance
+    pub fn new(n: usize) -> Self {
+        Self {
+            n: n.max(2),
+            population: false,
+            window: VecDeque::with_capacity(n),
+            sum: Decimal::zero(),
+            sum_sq: Decimal::zero(),
+            field: PriceField::default(),
+        }
+    }
+
+    /// n denominator instead - what bollinger bands use
+    pub fn population(mut self) -> Self {
+        self.population = true;
+        self
+    }
+
+    pub fn on(mut self, field: PriceField) -> Self {
+        self.field = field;
+        self
+    }
+}
+
+impl StreamingIndicator for RollingVariance {
+    type Output = Decimal;
+
+    fn push(&mut self, value: Decimal) -> Option<Decimal> {
+        self.window.push_back(value);
+        self.sum += value;
+        self.sum_sq += value * value;
+        if self.window.len() > self.n {
+            let old = self.window.pop_front().unwrap_or_default();
+            self.sum -= old;
+            self.sum_sq -= old * old;
+        }
+        if self.window.len() < self.n {
+            return None;
+        }
+        let n = Decimal::from(self.n);
+        // one division at the end so the sums stay exact as long as possible
+        let denominator = if self.population {
+            n * n
+        } else {
+            n * (n - Decimal::one())
+        };
+        let variance = (n * self.sum_sq - self.sum * self.sum) / denominator;
+        Some(variance.max(Decimal::zero()))
+    }
+
+    fn field(&self) -> PriceField {
+        self.field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::*;
+    use crate::process_data::n_window_sma;
+
+    /// a zig-zag with repeats, so windows have ties for both the min and the max
+    fn series() -> Vec<Decimal> {
+        [5, 3, 3, 8, 1, 8, 8, 2, 7, 7, 7, 4, 9, 0, 6, 6]
+            .iter()
+            .map(|&v| Decimal::from(v))
+            .collect()
+    }
+
+    /// the values as daily bars' closes - everything else is noise the indicators shouldn't read
+    fn quotes(values: &[Decimal]) -> Vec<YQuote> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| YQuote {
+                timestamp: Utc.ymd(2021, 1, 4).and_hms(21, 0, 0) + Duration::days(i as i64),
+                open: Decimal::from(1000),
+                high: Decimal::from(1000),
+                low: Decimal::from(-1000),
+                volume: 1,
+                close,
+                adjclose: Decimal::from(-1),
+            })
+            .collect()
+    }
+
+    /// update() one bar at a time, keeping the warmed-up outputs like `run` does
+    fn one_at_a_time<I: StreamingIndicator>(mut indicator: I, quotes: &[YQuote]) -> Vec<I::Output> {
+        quotes.iter().filter_map(|q| indicator.update(q)).collect()
+    }
+
+    fn mean(w: &[Decimal]) -> Decimal {
+        w.iter().sum::<Decimal>() / Decimal::from(w.len())
+    }
+
+    /// two-pass variance - the mean first, then the squared distances from it
+    fn two_pass_variance(w: &[Decimal], population: bool) -> Decimal {
+        let m = mean(w);
+        let sum_sq: Decimal = w.iter().map(|v| (v - m) * (v - m)).sum();
+        let n = w.len() - if population { 0 } else { 1 };
+        sum_sq / Decimal::from(n)
+    }
+
+    #[test]
+    fn sma_bar_by_bar_is_the_batch_result() {
+        let values = series();
+        for n in 1..=5 {
+            let streamed =
+                one_at_a_time(RollingSma::new(n).on(PriceField::Close), &quotes(&values));
+            let naive: Vec<Decimal> = values.windows(n).map(mean).collect();
+            assert_eq!(streamed, naive, "n = {}", n);
+            assert_eq!(Some(streamed), n_window_sma(n, &values));
+        }
+    }
+
+    #[test]
+    fn min_max_bar_by_bar_is_the_naive_windows() {
+        let values = series();
+        for n in 1..=6 {
+            let streamed = one_at_a_time(
+                RollingMinMax::new(n).on(PriceField::Close),
+                &quotes(&values),
+            );
+            let naive: Vec<(Decimal, Decimal)> = values
+                .windows(n)
+                .map(|w| (*w.iter().min().unwrap(), *w.iter().max().unwrap()))
+                .collect();
+            assert_eq!(streamed, naive, "n = {}", n);
+        }
+        let expanding = run(RollingMinMax::expanding(), &values);
+        assert_eq!(expanding.len(), values.len());
+        assert_eq!(expanding[3], (Decimal::from(3), Decimal::from(8)));
+        assert_eq!(expanding[13], (Decimal::from(0), Decimal::from(9)));
+    }
+
+    #[test]
+    fn variance_bar_by_bar_is_the_two_pass_result() {
+        let values = series();
+        for n in 2..=6 {
+            for population in [false, true] {
+                let indicator = RollingVariance::new(n).on(PriceField::Close);
+                let indicator = if population {
+                    indicator.population()
+                } else {
+                    indicator
+                };
+                let streamed = one_at_a_time(indicator, &quotes(&values));
+                let naive: Vec<Decimal> = values
+                    .windows(n)
+                    .map(|w| two_pass_variance(w, population))
+                    .collect();
+                assert_eq!(streamed.len(), naive.len());
+                for (s, v) in streamed.iter().zip(&naive) {
+                    assert!(
+                        (s - v).abs() < Decimal::new(1, 20),
+                        "n = {}, population = {}: {} vs {}",
+                        n,
+                        population,
+                        s,
+                        v
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ema_bar_by_bar_is_the_batch_result() {
+        let values = series();
+        let streamed = one_at_a_time(StreamingEma::new(4).on(PriceField::Close), &quotes(&values));
+        assert_eq!(streamed, run(StreamingEma::new(4), &values));
+        // seeded with the sma of the first 4, then 2/5 of the way to each new value
+        assert_eq!(streamed[0], Decimal::new(475, 2));
+        assert_eq!(
+            streamed[1],
+            Decimal::new(475, 2) + Decimal::new(4, 1) * Decimal::new(-375, 2)
+        );
+    }
+
+    #[test]
+    fn update_reads_the_configured_field() {
+        let quotes = quotes(&series());
+        let mut sma = RollingSma::new(1);
+        assert_eq!(sma.update(&quotes[0]), Some(Decimal::from(-1)));
+        let mut sma = RollingSma::new(1).on(PriceField::Volume);
+        assert_eq!(sma.update(&quotes[0]), Some(Decimal::one()));
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::interval::Interval;
+
+/// Which zone bar times are printed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputZone {
+    /// wherever the ticker trades
+    #[default]
+    Exchange,
+    Utc,
+    Zone(Tz),
+}
+
+impl OutputZone {
+    pub fn resolve(&self, exchange: Tz) -> Tz {
+        match self {
+            OutputZone::Exchange => exchange,
+            OutputZone::Utc => Tz::UTC,
+            OutputZone::Zone(tz) => *tz,
+        }
+    }
+
+    /// How a bar is labelled in the output. Daily and longer bars go by their trading date at the exchange -
+    /// shifting those into another zone would only move them onto the wrong day. Intraday bars get their
+    /// start time in this zone.
+    pub fn format_bar(&self, timestamp: DateTime<Utc>, exchange: Tz, interval: Interval) -> String {
+        match interval {
+            Interval::OneMinute | Interval::FiveMinutes | Interval::OneHour => timestamp
+                .with_timezone(&self.resolve(exchange))
+                .to_rfc3339(),
+            _ => timestamp
+                .with_timezone(&exchange)
+                .date()
+                .naive_local()
+                .to_string(),
+        }
+    }
+}
+
+impl fmt::Display for OutputZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputZone::Exchange => f.write_str("exchange"),
+            OutputZone::Utc => f.write_str("utc"),
+            OutputZone::Zone(tz) => f.write_str(tz.name()),
+        }
+    }
+}
+
+impl FromStr for OutputZone {
+    type Err = String;
+
+    /// "exchange", "utc" or an IANA name like "Asia/Tokyo"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exchange" | "local" => Ok(OutputZone::Exchange),
+            "utc" => Ok(OutputZone::Utc),
+            _ => s.parse().map(OutputZone::Zone).map_err(|_| {
+                format!(
+                    "unknown timezone '{}' - use exchange, utc or an IANA name like Asia/Tokyo",
+                    s
+                )
+            }),
+        }
+    }
+}